@@ -0,0 +1,197 @@
+//! Username/password login and session token management.
+
+use std::{fmt::Write, path::Path, time::Duration};
+
+use anyhow::{Result, anyhow};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use super::{
+    AlistClient,
+    types::{LoginRequest, LoginResponse},
+};
+use crate::{Config, utils::file_ops::write_atomic_private};
+
+/// Salt AList appends to the password before hashing it for
+/// `/api/auth/login/hash`
+const PASSWORD_SALT: &str = "-https://github.com/alist-org/alist";
+
+/// Returns the token a new client starts with: the configured one, or the
+/// cached token file
+pub(crate) fn initial_token(config: &Config) -> String {
+    if !config.token.is_empty() {
+        return config.token.clone();
+    }
+    config
+        .token_file
+        .as_deref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|token| token.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Minimal view of an API response used to detect authentication failures
+#[derive(Deserialize)]
+struct ResponseCode {
+    code: u32,
+}
+
+/// Hashes a password the way the AList web UI does for hashed login
+///
+/// # Arguments
+///
+/// * `password` - The plain password
+///
+/// # Returns
+///
+/// The hex encoded SHA-256 of the salted password
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(format!("{password}{PASSWORD_SALT}"))
+        .iter()
+        .fold(String::new(), |mut output, byte| {
+            let _ = write!(output, "{byte:02x}");
+            output
+        })
+}
+
+/// Writes a token to the token file, readable only by the current user
+///
+/// See [`write_atomic_private`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written
+pub async fn save_token(path: &Path, token: &str) -> Result<()> {
+    write_atomic_private(path, token.as_bytes()).await
+}
+
+/// Checks if a response means the token is missing, invalid or expired.
+///
+/// AList reports this either as HTTP 401 or as HTTP 200 with `code` 401 in
+/// the body.
+///
+/// # Arguments
+///
+/// * `status` - HTTP status of the response
+/// * `body` - Raw response body
+pub fn is_auth_failure(status: StatusCode, body: &[u8]) -> bool {
+    status == StatusCode::UNAUTHORIZED ||
+        serde_json::from_slice::<ResponseCode>(body).is_ok_and(|response| response.code == 401)
+}
+
+impl AlistClient {
+    /// Logs in with a username and password.
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - Account name
+    /// * `password` - Plain password
+    /// * `otp_code` - One-time code for accounts with two-factor authentication
+    /// * `hashed` - Send a salted hash via `/api/auth/login/hash` instead of
+    ///   the plain password
+    ///
+    /// # Returns
+    ///
+    /// The session token (JWT)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects the login
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        otp_code: Option<&str>,
+        hashed: bool,
+    ) -> Result<String> {
+        let (endpoint, password) = if hashed {
+            ("/api/auth/login/hash", hash_password(password))
+        } else {
+            ("/api/auth/login", password.to_string())
+        };
+        let payload = LoginRequest {
+            username: username.to_string(),
+            password,
+            otp_code: otp_code.unwrap_or_default().to_string(),
+        };
+
+        self.wait_for_permit().await?;
+        let response = self
+            .http()
+            .post(self.api_url(endpoint))
+            .timeout(Duration::from_secs(self.config().timeout))
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Login failed with HTTP {}", response.status()));
+        }
+
+        let login_response: LoginResponse = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse login response: {}", e))?;
+        match login_response.data {
+            Some(data) if login_response.code == 200 => {
+                debug!("Logged in as {}", username);
+                Ok(data.token)
+            }
+            _ => Err(anyhow!(
+                "Login failed with code {}: {}",
+                login_response.code,
+                login_response.message
+            )),
+        }
+    }
+
+    /// Returns the token to send with API requests
+    pub async fn current_token(&self) -> String {
+        self.token_lock().read().await.clone()
+    }
+
+    /// Replaces the token sent with API requests
+    pub async fn set_token(&self, token: String) {
+        *self.token_lock().write().await = token;
+    }
+
+    /// Checks if credentials are configured for automatic re-authentication
+    pub fn can_refresh(&self) -> bool {
+        let config = self.config();
+        config.username.is_some() && config.password.is_some()
+    }
+
+    /// Logs in again with the configured credentials and caches the new token.
+    ///
+    /// # Arguments
+    ///
+    /// * `stale_token` - The token that was rejected; if another request
+    ///   already replaced it, no new login is made
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no credentials are configured or the login fails
+    pub async fn refresh_token(&self, stale_token: &str) -> Result<()> {
+        let _guard = self.login_lock().lock().await;
+        if self.current_token().await != stale_token {
+            return Ok(());
+        }
+
+        let config = self.config();
+        let (Some(username), Some(password)) = (&config.username, &config.password) else {
+            return Err(anyhow!("Token rejected and no credentials configured"));
+        };
+
+        info!("Token expired or invalid, logging in again as {}", username);
+        let token = self.login(username, password, None, true).await?;
+        if let Some(path) = &config.token_file &&
+            let Err(e) = save_token(path, &token).await
+        {
+            warn!("Failed to cache token in {}: {}", path.display(), e);
+        }
+        self.set_token(token).await;
+        Ok(())
+    }
+}
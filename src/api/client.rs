@@ -2,21 +2,29 @@
 
 use std::{
     collections::{HashSet, VecDeque},
+    pin::pin,
     sync::Arc,
     time::Duration,
 };
 
 use anyhow::{Result, anyhow};
+use futures::{
+    SinkExt, Stream,
+    channel::mpsc,
+    stream::{self, FuturesUnordered, StreamExt},
+};
+use governor::DefaultDirectRateLimiter;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use reqwest::Client;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, error, info, trace, warn};
 
 use super::{
-    rate_limiter::rate_limited_request,
-    types::{ApiData, ApiResponse, EntryWithPath, FileInfoRequest},
+    auth,
+    rate_limiter::new_rate_limiter,
+    types::{ApiData, ApiResponse, EntryWithPath, FileInfoRequest, ListDirError, PathStructure},
 };
-use crate::get_config;
+use crate::{Config, snapshot::Snapshot, utils::hash_cache::HashCache};
 
 /// Maximum number of retry attempts for failed requests
 const MAX_RETRIES: u32 = 3;
@@ -27,266 +35,535 @@ const INITIAL_BACKOFF_MS: u64 = 500;
 /// Maximum backoff delay in milliseconds
 const MAX_BACKOFF_MS: u64 = 10000;
 
-/// Retrieves the complete directory structure from the Alist server.
-///
-/// This function recursively traverses the directory structure starting from
-/// the given path and returns all files and directories found.
-///
-/// # Arguments
-///
-/// * `path` - The starting path to scan
-/// * `m_pb` - Multi-progress bar for UI feedback
-/// * `client` - HTTP client for making requests
-///
-/// # Returns
-///
-/// A vector of all entries found with their full paths
-///
-/// # Errors
+/// Number of discovered entries buffered ahead of a slow stream consumer
+pub const STREAM_BUFFER: usize = 1024;
+
+/// Client for one Alist server.
 ///
-/// Returns an error if the API requests fail or if there are network issues
-pub async fn get_path_structure(
-    path: String,
-    m_pb: MultiProgress,
-    client: Arc<Client>,
-) -> Result<Vec<EntryWithPath>> {
-    let visited_paths = Arc::new(Mutex::new(HashSet::new()));
-    {
-        let mut visited_paths_lock = visited_paths.lock().await;
-        visited_paths_lock.insert(path.clone());
+/// Owns the HTTP client, the configuration, the request rate limiter and the
+/// session token. Cloning is cheap and clones share all of that state, so
+/// several clients for different servers can live in one process.
+#[derive(Clone)]
+pub struct AlistClient {
+    inner: Arc<ClientInner>,
+}
+
+/// State shared between clones of an [`AlistClient`]
+struct ClientInner {
+    http: Client,
+    config: Config,
+    rate_limiter: DefaultDirectRateLimiter,
+    /// Token sent in the `Authorization` header
+    token: RwLock<String>,
+    /// Serializes re-authentication so concurrent requests log in only once
+    login_lock: Mutex<()>,
+    /// Digests of local files, see [`HashCache`]
+    hash_cache: HashCache,
+}
+
+impl AlistClient {
+    /// Creates a client with its own HTTP client.
+    ///
+    /// The HTTP client gives up connecting after `config.connect_timeout`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Server address, credentials and limits to use
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be built
+    pub fn new(config: Config) -> Result<Self> {
+        let http = Client::builder()
+            .no_proxy()
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .build()?;
+        Ok(Self::with_http_client(config, http))
     }
 
-    // Fetch the folder contents iteratively and get all entries with paths
-    let entries_with_paths =
-        fetch_folder_contents(path, visited_paths.clone(), m_pb, client).await?;
+    /// Creates a client on top of an existing HTTP client.
+    ///
+    /// The initial token is `config.token`, or the contents of
+    /// `config.token_file` if no token is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Server address, credentials and limits to use
+    /// * `http` - HTTP client for all requests
+    pub fn with_http_client(config: Config, http: Client) -> Self {
+        let token = auth::initial_token(&config);
+        let hash_cache = HashCache::load(config.hash_cache.clone());
+        Self {
+            inner: Arc::new(ClientInner {
+                rate_limiter: new_rate_limiter(config.tpslimit),
+                http,
+                config,
+                token: RwLock::new(token),
+                login_lock: Mutex::new(()),
+                hash_cache,
+            }),
+        }
+    }
 
-    // Return the collected entries along with their paths
-    Ok(entries_with_paths)
-}
+    /// Returns the configuration of this client
+    pub fn config(&self) -> &Config {
+        &self.inner.config
+    }
 
-/// Makes an API request to get directory contents.
-///
-/// # Arguments
-///
-/// * `client` - HTTP client for making requests
-/// * `payload` - Request payload with path and pagination info
-///
-/// # Returns
-///
-/// The parsed API response
-///
-/// # Errors
-///
-/// Returns an error if the request fails or response parsing fails
-async fn get_api_response(client: &Client, payload: &FileInfoRequest) -> Result<ApiResponse> {
-    let response = rate_limited_request(
-        client,
-        format!("{}/api/fs/list", get_config().server_address),
-        payload,
-    )
-    .await?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!("HTTP error: {}", response.status()));
+    /// Returns the underlying HTTP client
+    pub fn http(&self) -> &Client {
+        &self.inner.http
+    }
+
+    /// Returns the request rate limiter
+    pub(crate) fn rate_limiter(&self) -> &DefaultDirectRateLimiter {
+        &self.inner.rate_limiter
     }
 
-    let api_response: ApiResponse = response
-        .json()
-        .await
-        .map_err(|e| anyhow!("Failed to parse API response: {}", e))?;
+    /// Returns the session token lock
+    pub(crate) fn token_lock(&self) -> &RwLock<String> {
+        &self.inner.token
+    }
+
+    /// Returns the lock held while logging in again
+    pub(crate) fn login_lock(&self) -> &Mutex<()> {
+        &self.inner.login_lock
+    }
 
-    trace!("list api_response: {:?}", api_response);
-    Ok(api_response)
+    /// Returns the cache of local file digests
+    pub fn hash_cache(&self) -> &HashCache {
+        &self.inner.hash_cache
+    }
+
+    /// Builds the URL of an API endpoint on this server
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - Path of the endpoint, e.g. `/api/fs/list`
+    pub fn api_url(&self, endpoint: &str) -> String {
+        format!("{}{}", self.config().server_address, endpoint)
+    }
+
+    /// Checks that the server is up and answering requests
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `/ping` fails or does not answer within the API
+    /// timeout
+    pub async fn ping(&self) -> Result<()> {
+        let response = self
+            .http()
+            .get(self.api_url("/ping"))
+            .timeout(Duration::from_secs(self.config().timeout))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Ping failed with HTTP {}", response.status()));
+        }
+        Ok(())
+    }
 }
 
-/// Processes the contents of a single folder with retry logic.
-///
-/// # Arguments
-///
-/// * `client` - HTTP client for making requests
-/// * `current_path` - Path of the folder to process
-/// * `payload` - Request payload for the API call
-/// * `entries_with_paths` - Vector to collect found entries
-/// * `directories_to_process` - Queue of directories still to process
-/// * `visited_paths` - Set of already visited paths to avoid cycles
-/// * `pb` - Progress bar for UI feedback
-///
-/// # Returns
-///
-/// Success if the folder was processed successfully
-///
-/// # Errors
-///
-/// Returns an error if all retry attempts fail
-async fn process_folder_contents(
-    client: &Client,
-    current_path: &str,
-    payload: &FileInfoRequest,
-    entries_with_paths: &mut Vec<EntryWithPath>,
-    directories_to_process: &mut VecDeque<String>,
-    visited_paths: &Arc<Mutex<HashSet<String>>>,
-    pb: &ProgressBar,
-) -> Result<()> {
-    let mut retry_count = 0;
-
-    while retry_count <= MAX_RETRIES {
-        // Break early if this is a retry attempt
-        if retry_count > 0 {
-            // Calculate exponential backoff delay
-            let backoff_ms = std::cmp::min(
-                INITIAL_BACKOFF_MS * 2_u64.pow(retry_count - 1),
-                MAX_BACKOFF_MS,
-            );
-            info!(
-                "Retrying request for path {} ({}/{}) in {}ms",
-                current_path, retry_count, MAX_RETRIES, backoff_ms
+impl AlistClient {
+    /// Retrieves the complete directory structure from the Alist server.
+    ///
+    /// This function recursively traverses the directory structure starting
+    /// from the given path and returns all files and directories found. Use
+    /// [`AlistClient::stream_path_structure`] to process entries while the
+    /// scan is still running instead of buffering the whole tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The starting path to scan
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// All entries found with their full paths, plus the directories that could
+    /// not be listed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API requests fail or if there are network issues
+    pub async fn get_path_structure(
+        &self,
+        path: String,
+        m_pb: MultiProgress,
+    ) -> Result<PathStructure> {
+        let mut structure = PathStructure::default();
+        let mut entries = pin!(self.stream_path_structure(path, m_pb));
+        while let Some(result) = entries.next().await {
+            match result {
+                Ok(entry) => structure.entries.push(entry),
+                Err(err) => structure
+                    .failed_dirs
+                    .push(err.downcast::<ListDirError>()?.path),
+            }
+        }
+
+        if !structure.failed_dirs.is_empty() {
+            warn!(
+                "{} directories could not be listed, their contents are unknown",
+                structure.failed_dirs.len()
             );
-            tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
         }
 
-        // Attempt to get API response
-        let api_response = match get_api_response(client, payload).await {
-            Ok(response) => response,
-            Err(err) => {
-                warn!("Request failed: {}", err);
+        // Return the collected entries along with their paths
+        Ok(structure)
+    }
+
+    /// Traverses the directory structure, yielding entries as they are
+    /// discovered.
+    ///
+    /// The scan runs in a background task that stays at most
+    /// [`STREAM_BUFFER`] entries ahead of the consumer, and stops once the
+    /// stream is dropped. Must be called within a Tokio runtime.
+    ///
+    /// Entries rejected by the configured [`crate::filter::PathFilter`] are
+    /// skipped, and excluded directories are not listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The starting path to scan
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// A stream of entries with their full paths. A directory that could not
+    /// be listed yields an error wrapping a [`ListDirError`] and the scan
+    /// continues with the next directory.
+    pub fn stream_path_structure(
+        &self,
+        path: String,
+        m_pb: MultiProgress,
+    ) -> impl Stream<Item = Result<EntryWithPath>> + Send + 'static {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let client = self.clone();
+        tokio::spawn(async move { client.fetch_folder_contents(path, None, tx, m_pb).await });
+        rx
+    }
+
+    /// Like [`AlistClient::stream_path_structure`], but reuses the listings
+    /// of a previous scan for directories whose `modified` timestamp did not
+    /// change. Only a timestamp from a fresh listing of the parent counts, so
+    /// the subdirectories of a reused listing are always listed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The starting path to scan; it is always listed
+    /// * `snapshot` - The previous scan
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// A stream of current and reused entries with their full paths
+    pub fn stream_path_structure_incremental(
+        &self,
+        path: String,
+        snapshot: Arc<Snapshot>,
+        m_pb: MultiProgress,
+    ) -> impl Stream<Item = Result<EntryWithPath>> + Send + 'static {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let client = self.clone();
+        tokio::spawn(async move {
+            client
+                .fetch_folder_contents(path, Some(snapshot), tx, m_pb)
+                .await
+        });
+        rx
+    }
+
+    /// Makes an API request to get directory contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - Request payload with path and pagination info
+    ///
+    /// # Returns
+    ///
+    /// The parsed API response
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or response parsing fails
+    pub(crate) async fn get_api_response(&self, payload: &FileInfoRequest) -> Result<ApiResponse> {
+        let response = self
+            .rate_limited_request(self.api_url("/api/fs/list"), payload)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("HTTP error: {}", response.status()));
+        }
+
+        let api_response: ApiResponse = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse API response: {}", e))?;
+
+        trace!("list api_response: {:?}", api_response);
+        Ok(api_response)
+    }
+
+    /// Processes the contents of a single folder with retry logic.
+    ///
+    /// # Arguments
+    ///
+    /// * `current_path` - Path of the folder to process
+    /// * `payload` - Request payload for the API call
+    /// * `entries_with_paths` - Vector to collect found entries
+    /// * `directories_to_process` - Queue of directories still to process, with
+    ///   their `modified` timestamps
+    /// * `visited_paths` - Set of already visited paths to avoid cycles
+    /// * `pb` - Progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// Success if the folder was processed successfully
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if all retry attempts fail
+    async fn process_folder_contents(
+        &self,
+        current_path: &str,
+        payload: &FileInfoRequest,
+        entries_with_paths: &mut Vec<EntryWithPath>,
+        directories_to_process: &mut VecDeque<(String, Option<String>)>,
+        visited_paths: &Arc<Mutex<HashSet<String>>>,
+        pb: &ProgressBar,
+    ) -> Result<()> {
+        let mut retry_count = 0;
+
+        while retry_count <= MAX_RETRIES {
+            // Break early if this is a retry attempt
+            if retry_count > 0 {
+                // Calculate exponential backoff delay
+                let backoff_ms = std::cmp::min(
+                    INITIAL_BACKOFF_MS * 2_u64.pow(retry_count - 1),
+                    MAX_BACKOFF_MS,
+                );
+                info!(
+                    "Retrying request for path {} ({}/{}) in {}ms",
+                    current_path, retry_count, MAX_RETRIES, backoff_ms
+                );
+                tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+            }
+
+            // Attempt to get API response
+            let api_response = match self.get_api_response(payload).await {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!("Request failed: {}", err);
+                    retry_count += 1;
+                    if retry_count > MAX_RETRIES {
+                        error!("Failed after {} retries: {}", MAX_RETRIES, current_path);
+                        return Err(err);
+                    }
+                    continue;
+                }
+            };
+
+            // Check for error codes in API response
+            if api_response.code != 200 {
+                warn!(
+                    "API returned error code {}: {}",
+                    api_response.code, api_response.message
+                );
                 retry_count += 1;
                 if retry_count > MAX_RETRIES {
                     error!("Failed after {} retries: {}", MAX_RETRIES, current_path);
-                    return Err(err);
+                    return Err(anyhow!(
+                        "API error code {}: {}",
+                        api_response.code,
+                        api_response.message
+                    ));
                 }
                 continue;
             }
-        };
 
-        // Check for error codes in API response
-        if api_response.code != 200 {
-            warn!(
-                "API returned error code {}: {}",
-                api_response.code, api_response.message
-            );
-            retry_count += 1;
-            if retry_count > MAX_RETRIES {
-                error!("Failed after {} retries: {}", MAX_RETRIES, current_path);
-                return Err(anyhow!(
-                    "API error code {}: {}",
-                    api_response.code,
-                    api_response.message
-                ));
+            // Process the response data
+            match api_response.data {
+                Some(ApiData::FoldersInfo(folders_info)) => {
+                    // Skip if no content
+                    let Some(content) = &folders_info.content else {
+                        return Ok(());
+                    };
+
+                    for file in content {
+                        let full_path =
+                            format!("{}/{}", current_path.trim_end_matches('/'), file.name);
+                        debug!("entry path: {}", full_path);
+                        pb.set_message(format!("Scanning: {full_path}"));
+
+                        let entry = EntryWithPath {
+                            entry: file.clone(),
+                            path_str: full_path.clone(),
+                            provider: folders_info.provider.clone(),
+                        };
+                        // Filtered out directories are never listed
+                        if !self.config().filter.allows(&entry) {
+                            trace!("Filtered out {}", full_path);
+                            continue;
+                        }
+
+                        // Add this entry and its full path to the list
+                        entries_with_paths.push(entry);
+
+                        // If the item is a directory and hasn't been visited, add it to the queue
+                        if file.is_dir {
+                            let mut visited = visited_paths.lock().await;
+                            if visited.insert(full_path.clone()) {
+                                directories_to_process
+                                    .push_back((full_path, Some(file.modified.clone())));
+                            }
+                        }
+                        pb.inc(1);
+                    }
+
+                    return Ok(());
+                }
+                _ => {
+                    retry_count += 1;
+                    if retry_count > MAX_RETRIES {
+                        error!("Failed after {} retries: {}", MAX_RETRIES, current_path);
+                        return Err(anyhow!("Invalid data format in API response"));
+                    }
+                    continue;
+                }
             }
-            continue;
         }
 
-        // Process the response data
-        match api_response.data {
-            Some(ApiData::FoldersInfo(folders_info)) => {
-                // Skip if no content
-                let Some(content) = &folders_info.content else {
-                    return Ok(());
-                };
-
-                for file in content {
-                    let full_path = format!("{}/{}", current_path, file.name);
-                    debug!("entry path: {}", full_path);
-                    pb.set_message(format!("Scanning: {full_path}"));
-
-                    // Add this entry and its full path to the list
-                    entries_with_paths.push(EntryWithPath {
-                        entry: file.clone(),
-                        path_str: full_path.clone(),
-                        provider: folders_info.provider.clone(),
-                    });
-
-                    // If the item is a directory and hasn't been visited, add it to the queue
-                    if file.is_dir {
+        Err(anyhow!("Failed to process directory after maximum retries"))
+    }
+
+    /// Fetches folder contents recursively using breadth-first traversal.
+    ///
+    /// Up to `concurrent_limit` directories are listed at the same time. Every
+    /// request still goes through the client's rate limiter, and
+    /// `visited_paths` is shared between workers so no directory is listed
+    /// twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Starting path to fetch from
+    /// * `snapshot` - Previous scan whose listings are reused for directories
+    ///   with an unchanged `modified` timestamp
+    /// * `tx` - Channel receiving every found entry, and a [`ListDirError`] for
+    ///   every directory that failed to list
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    async fn fetch_folder_contents(
+        &self,
+        path: String,
+        snapshot: Option<Arc<Snapshot>>,
+        mut tx: mpsc::Sender<Result<EntryWithPath>>,
+        m_pb: MultiProgress,
+    ) {
+        let visited_paths = Arc::new(Mutex::new(HashSet::from([path.clone()])));
+        let mut directories_to_process: VecDeque<(String, Option<String>)> = VecDeque::new();
+        directories_to_process.push_back((path, None));
+
+        let spinner_style =
+            ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{len}] {wide_msg}")
+                .unwrap();
+        let pb = m_pb.add(ProgressBar::new_spinner());
+        pb.set_style(spinner_style.clone());
+        pb.enable_steady_tick(Duration::from_millis(100));
+
+        let concurrent_limit = self.config().concurrent_limit.max(1);
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            // Keep up to `concurrent_limit` directory listings running at once
+            while in_flight.len() < concurrent_limit &&
+                let Some((current_path, modified)) = directories_to_process.pop_front()
+            {
+                let visited_paths = Arc::clone(&visited_paths);
+                let snapshot = snapshot.clone();
+                let pb = pb.clone();
+                in_flight.push(async move {
+                    let mut entries = Vec::new();
+                    let mut subdirectories = VecDeque::new();
+
+                    // Reuse the previous listing if the directory is unchanged
+                    if let Some(snapshot) = &snapshot &&
+                        let Some(modified) = modified.as_deref() &&
+                        let Some(listing) = snapshot.listing(&current_path, modified)
+                    {
+                        trace!("Reusing listing of unchanged directory {}", current_path);
                         let mut visited = visited_paths.lock().await;
-                        if visited.insert(full_path.clone()) {
-                            directories_to_process.push_back(full_path);
+                        for entry in listing {
+                            // The filter may have changed since the listing was recorded
+                            if !self.config().filter.allows(entry) {
+                                continue;
+                            }
+                            // The recorded timestamp would always match the
+                            // snapshot, hiding changes further down, so
+                            // subdirectories are listed again
+                            if entry.entry.is_dir && visited.insert(entry.path_str.clone()) {
+                                subdirectories.push_back((entry.path_str.clone(), None));
+                            }
+                            entries.push(entry.clone());
+                            pb.inc(1);
                         }
+                        return (current_path, Ok((entries, subdirectories)));
                     }
-                    pb.inc(1);
-                }
 
-                return Ok(());
+                    // Prepare the JSON payload
+                    let payload = FileInfoRequest {
+                        path: current_path.clone(),
+                        password: "".to_string(),
+                        page: 1,
+                        per_page: 0,
+                        refresh: false,
+                    };
+                    trace!("Payload: {:?}", payload);
+
+                    let result = self
+                        .process_folder_contents(
+                            &current_path,
+                            &payload,
+                            &mut entries,
+                            &mut subdirectories,
+                            &visited_paths,
+                            &pb,
+                        )
+                        .await;
+                    (current_path, result.map(|_| (entries, subdirectories)))
+                });
             }
-            _ => {
-                retry_count += 1;
-                if retry_count > MAX_RETRIES {
-                    error!("Failed after {} retries: {}", MAX_RETRIES, current_path);
-                    return Err(anyhow!("Invalid data format in API response"));
+
+            let Some((current_path, result)) = in_flight.next().await else {
+                break;
+            };
+
+            let sent = match result {
+                Ok((entries, subdirectories)) => {
+                    directories_to_process.extend(subdirectories);
+                    tx.send_all(&mut stream::iter(entries.into_iter().map(|e| Ok(Ok(e)))))
+                        .await
                 }
-                continue;
+                Err(err) => {
+                    warn!(
+                        "Failed to process path after {} retries: {}",
+                        MAX_RETRIES, current_path
+                    );
+                    debug!("Error details: {:?}", err);
+                    // Continue with next directory, but report that this subtree is unknown
+                    let err = ListDirError {
+                        path: current_path,
+                        source: err,
+                    };
+                    tx.send(Err(err.into())).await
+                }
+            };
+
+            // The consumer dropped the stream, stop scanning
+            if sent.is_err() {
+                pb.finish_and_clear();
+                return;
             }
         }
-    }
-
-    Err(anyhow!("Failed to process directory after maximum retries"))
-}
 
-/// Fetches folder contents recursively using breadth-first traversal.
-///
-/// # Arguments
-///
-/// * `path` - Starting path to fetch from
-/// * `visited_paths` - Set of already visited paths
-/// * `m_pb` - Multi-progress bar for UI feedback
-/// * `client` - HTTP client for requests
-///
-/// # Returns
-///
-/// Vector of all found entries with their paths
-///
-/// # Errors
-///
-/// Returns an error if critical API calls fail
-async fn fetch_folder_contents(
-    path: String,
-    visited_paths: Arc<Mutex<HashSet<String>>>,
-    m_pb: MultiProgress,
-    client: Arc<Client>,
-) -> Result<Vec<EntryWithPath>> {
-    let mut entries_with_paths = Vec::new();
-    let mut directories_to_process = VecDeque::new();
-    directories_to_process.push_back(path.clone());
-
-    let spinner_style =
-        ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{len}] {wide_msg}")
-            .unwrap();
-    let pb = m_pb.add(ProgressBar::new_spinner());
-    pb.set_style(spinner_style.clone());
-    pb.enable_steady_tick(Duration::from_millis(100));
-
-    // Process the directories iteratively using a queue
-    while let Some(current_path) = directories_to_process.pop_front() {
-        // Prepare the JSON payload
-        let payload = FileInfoRequest {
-            path: current_path.clone(),
-            password: "".to_string(),
-            page: 1,
-            per_page: 0,
-            refresh: false,
-        };
-        trace!("Payload: {:?}", payload);
-
-        if let Err(err) = process_folder_contents(
-            &client,
-            &current_path,
-            &payload,
-            &mut entries_with_paths,
-            &mut directories_to_process,
-            &visited_paths,
-            &pb,
-        )
-        .await
-        {
-            warn!(
-                "Failed to process path after {} retries: {}",
-                MAX_RETRIES, current_path
-            );
-            debug!("Error details: {:?}", err);
-            // Continue with next directory instead of returning error
-        }
+        pb.finish_with_message(format!("Processed {} files", pb.position()));
     }
-
-    pb.finish_with_message(format!("Processed {} files", pb.position()));
-    Ok(entries_with_paths)
 }
@@ -0,0 +1,537 @@
+//! Changes to files on the server: mkdir, rename, move, copy and remove.
+//!
+//! Batch and regex renames are previewed from the directory listing before
+//! they are sent, see [`regex_renames`] and [`check_renames`].
+//!
+//! Commands taking several entries accept remote paths whose last component
+//! is a glob, e.g. `/incoming/*.mkv`, expanded against the listing of the
+//! parent directory.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
+use anyhow::{Result, anyhow};
+use globset::GlobBuilder;
+use regex::Regex;
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{debug, trace};
+
+use super::{
+    AlistClient,
+    types::{
+        ActionResponse, ApiData, BatchRenameRequest, EntryWithPath, FileInfoRequest, MkdirRequest,
+        MoveCopyRequest, RegexRenameRequest, RemoveRequest, RenameObject, RenameRequest, TaskInfo,
+        TasksData,
+    },
+};
+
+/// Splits a remote path into its parent directory and name
+///
+/// # Arguments
+///
+/// * `path` - Absolute remote path, trailing slashes are ignored
+///
+/// # Returns
+///
+/// The parent directory, `/` for top-level entries, and the name
+///
+/// # Errors
+///
+/// Returns an error if the path is relative or the root itself
+pub fn split_remote_path(path: &str) -> Result<(&str, &str)> {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rsplit_once('/') {
+        Some((_, "")) | None => Err(anyhow!(
+            "Expected an absolute path below the root, got '{}'",
+            path
+        )),
+        Some(("", name)) => Ok(("/", name)),
+        Some((dir, name)) => Ok((dir, name)),
+    }
+}
+
+/// Groups remote paths by their parent directory, as the move, copy and
+/// remove endpoints take one directory and a list of names
+///
+/// # Errors
+///
+/// Returns an error if a path is relative or the root itself
+pub fn group_by_dir<'a>(
+    paths: impl IntoIterator<Item = &'a str>,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for path in paths {
+        let (dir, name) = split_remote_path(path)?;
+        groups
+            .entry(dir.to_string())
+            .or_default()
+            .push(name.to_string());
+    }
+    Ok(groups)
+}
+
+/// Computes the renames `/api/fs/regex_rename` makes in a directory
+///
+/// Like the server, every name matching `regex` has all matches replaced by
+/// `replacement`. Names the replacement leaves as they are are skipped.
+///
+/// # Arguments
+///
+/// * `names` - Names of the entries in the directory
+/// * `regex` - Pattern the names must match
+/// * `replacement` - Replacement, `$1` or `${name}` refer to capture groups
+pub fn regex_renames<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    regex: &Regex,
+    replacement: &str,
+) -> Vec<RenameObject> {
+    names
+        .into_iter()
+        .filter(|name| regex.is_match(name))
+        .filter_map(|name| {
+            let new_name = regex.replace_all(name, replacement);
+            (new_name != name).then(|| RenameObject {
+                src_name: name.to_string(),
+                new_name: new_name.into_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Parses a list of renames, one `old name<TAB>new name` pair per line
+///
+/// Blank lines are ignored.
+///
+/// # Errors
+///
+/// Returns an error for a line without a tab
+pub fn parse_rename_list(content: &str) -> Result<Vec<RenameObject>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let (src_name, new_name) = line
+                .split_once('\t')
+                .ok_or_else(|| anyhow!("Line {}: expected 'old name<TAB>new name'", index + 1))?;
+            Ok(RenameObject {
+                src_name: src_name.to_string(),
+                new_name: new_name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Checks renames within one directory before they are sent.
+///
+/// The server renames entries one at a time in no particular order, so a
+/// new name may not be taken by any current entry, even one renamed away.
+///
+/// # Arguments
+///
+/// * `existing` - Names of the entries in the directory
+/// * `renames` - The renames to check
+///
+/// # Errors
+///
+/// Returns an error for the first rename whose entry is missing or renamed
+/// twice, or whose new name is invalid, already taken or given twice
+pub fn check_renames<'a>(
+    existing: impl IntoIterator<Item = &'a str>,
+    renames: &[RenameObject],
+) -> Result<()> {
+    let existing: HashSet<&str> = existing.into_iter().collect();
+    let mut sources = HashSet::new();
+    let mut targets = HashSet::new();
+    for rename in renames {
+        let RenameObject { src_name, new_name } = rename;
+        if !existing.contains(src_name.as_str()) {
+            return Err(anyhow!("'{}' does not exist", src_name));
+        }
+        if !sources.insert(src_name.as_str()) {
+            return Err(anyhow!("'{}' is renamed twice", src_name));
+        }
+        if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+            return Err(anyhow!(
+                "Invalid new name '{}' for '{}'",
+                new_name,
+                src_name
+            ));
+        }
+        if existing.contains(new_name.as_str()) {
+            return Err(anyhow!(
+                "Cannot rename '{}' to '{}', the name is taken",
+                src_name,
+                new_name
+            ));
+        }
+        if !targets.insert(new_name.as_str()) {
+            return Err(anyhow!("More than one entry would be named '{}'", new_name));
+        }
+    }
+    Ok(())
+}
+
+/// Writes a table of old and new names
+///
+/// # Arguments
+///
+/// * `out` - Where to write the table
+/// * `dir` - Directory holding the entries
+/// * `renames` - The renames to show
+///
+/// # Errors
+///
+/// Returns an error if writing fails
+pub fn write_rename_preview(
+    out: &mut impl Write,
+    dir: &str,
+    renames: &[RenameObject],
+) -> Result<()> {
+    let width = renames
+        .iter()
+        .map(|rename| rename.src_name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("OLD".len());
+    writeln!(out, "{:<width$}  ->  NEW", "OLD")?;
+    for rename in renames {
+        writeln!(out, "{:<width$}  ->  {}", rename.src_name, rename.new_name)?;
+    }
+    writeln!(out, "{} renames in {}", renames.len(), dir)?;
+    Ok(())
+}
+
+impl AlistClient {
+    /// Posts a request to an endpoint that changes files or tasks and checks
+    /// that the server accepted it.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - Path of the endpoint with any query string, e.g.
+    ///   `/api/fs/remove`
+    /// * `payload` - The request body
+    ///
+    /// # Returns
+    ///
+    /// The `data` of the response, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server answers with a
+    /// code other than 200
+    pub(crate) async fn post_action<T, D>(&self, endpoint: &str, payload: &T) -> Result<Option<D>>
+    where
+        T: Serialize + std::fmt::Debug,
+        D: DeserializeOwned + std::fmt::Debug,
+    {
+        trace!("{} payload: {:?}", endpoint, payload);
+        let response = self
+            .rate_limited_request(self.api_url(endpoint), payload)
+            .await?;
+        Self::parse_action_response(endpoint, response).await
+    }
+
+    /// Checks the response of an endpoint answering with `code`, `message`
+    /// and `data`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP status is not a success, the body cannot
+    /// be parsed or its code is not 200
+    pub(crate) async fn parse_action_response<D>(
+        endpoint: &str,
+        response: reqwest::Response,
+    ) -> Result<Option<D>>
+    where
+        D: DeserializeOwned + std::fmt::Debug,
+    {
+        if !response.status().is_success() {
+            return Err(anyhow!("HTTP error: {}", response.status()));
+        }
+
+        let api_response: ActionResponse<D> = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse API response: {}", e))?;
+        trace!("{} api_response: {:?}", endpoint, api_response);
+        if api_response.code != 200 {
+            return Err(anyhow!(
+                "{} failed with code {}: {}",
+                endpoint,
+                api_response.code,
+                api_response.message
+            ));
+        }
+        Ok(api_response.data)
+    }
+
+    /// Lists the entries of one remote directory, without filtering or
+    /// recursion.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote directory to list
+    ///
+    /// # Returns
+    ///
+    /// The entries with their full paths
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or `path` is not a directory
+    pub async fn list_dir(&self, path: &str) -> Result<Vec<EntryWithPath>> {
+        let payload = FileInfoRequest {
+            path: path.to_string(),
+            password: "".to_string(),
+            page: 1,
+            per_page: 0,
+            refresh: false,
+        };
+        let api_response = self.get_api_response(&payload).await?;
+        if api_response.code != 200 {
+            return Err(anyhow!(
+                "Failed to list '{}': {}",
+                path,
+                api_response.message
+            ));
+        }
+        match api_response.data {
+            Some(ApiData::FoldersInfo(folders_info)) => Ok(folders_info
+                .content
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| EntryWithPath {
+                    path_str: format!("{}/{}", path.trim_end_matches('/'), entry.name),
+                    entry,
+                    provider: folders_info.provider.clone(),
+                })
+                .collect()),
+            _ => Err(anyhow!("'{}' is not a directory", path)),
+        }
+    }
+
+    /// Resolves remote paths whose last component may be a glob
+    ///
+    /// An entry whose name equals the last component is taken as is, so
+    /// names such as `[1080p] a.mkv` need no escaping.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - Absolute remote paths or globs
+    ///
+    /// # Returns
+    ///
+    /// The matching entries, in the order of the patterns
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a parent directory cannot be listed, a glob is
+    /// invalid or a pattern matches nothing
+    pub async fn expand_paths(&self, patterns: &[String]) -> Result<Vec<EntryWithPath>> {
+        let mut listings: HashMap<String, Vec<EntryWithPath>> = HashMap::new();
+        let mut matched = Vec::new();
+        for pattern in patterns {
+            let (dir, name) = split_remote_path(pattern)?;
+            if !listings.contains_key(dir) {
+                listings.insert(dir.to_string(), self.list_dir(dir).await?);
+            }
+            let listing = &listings[dir];
+
+            let found: Vec<_> = match listing.iter().find(|entry| entry.entry.name == name) {
+                Some(entry) => vec![entry.clone()],
+                None => {
+                    let glob = GlobBuilder::new(name)
+                        .literal_separator(true)
+                        .build()
+                        .map_err(|e| anyhow!("Invalid glob '{}': {}", name, e))?
+                        .compile_matcher();
+                    listing
+                        .iter()
+                        .filter(|entry| glob.is_match(&entry.entry.name))
+                        .cloned()
+                        .collect()
+                }
+            };
+            if found.is_empty() {
+                return Err(anyhow!("No remote entries match '{}'", pattern));
+            }
+            matched.extend(found);
+        }
+        Ok(matched)
+    }
+
+    /// Creates a remote directory, along with any missing parents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn mkdir(&self, path: &str) -> Result<()> {
+        let payload = MkdirRequest {
+            path: path.to_string(),
+        };
+        self.post_action::<_, serde::de::IgnoredAny>("/api/fs/mkdir", &payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Renames a remote file or directory in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Full path of the entry
+    /// * `name` - New name, without a directory
+    /// * `overwrite` - Replace an existing entry with the new name
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn rename(&self, path: &str, name: &str, overwrite: bool) -> Result<()> {
+        let payload = RenameRequest {
+            path: path.to_string(),
+            name: name.to_string(),
+            overwrite,
+        };
+        self.post_action::<_, serde::de::IgnoredAny>("/api/fs/rename", &payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Renames several entries of one remote directory in a single request.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Remote directory holding the entries
+    /// * `renames` - Old and new names
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn batch_rename(&self, dir: &str, renames: Vec<RenameObject>) -> Result<()> {
+        let payload = BatchRenameRequest {
+            src_dir: dir.to_string(),
+            rename_objects: renames,
+        };
+        self.post_action::<_, serde::de::IgnoredAny>("/api/fs/batch_rename", &payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Renames every entry of a remote directory whose name matches a regex,
+    /// see [`regex_renames`] for the renames this makes.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Remote directory holding the entries
+    /// * `pattern` - Pattern the names must match
+    /// * `replacement` - Replacement, `$1` or `${name}` refer to capture groups
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn regex_rename(&self, dir: &str, pattern: &str, replacement: &str) -> Result<()> {
+        let payload = RegexRenameRequest {
+            src_dir: dir.to_string(),
+            src_name_regex: pattern.to_string(),
+            new_name_regex: replacement.to_string(),
+        };
+        self.post_action::<_, serde::de::IgnoredAny>("/api/fs/regex_rename", &payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Moves entries from one remote directory into another.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_dir` - Directory holding the entries
+    /// * `dst_dir` - Directory receiving them
+    /// * `names` - Names of the entries in `src_dir`
+    /// * `overwrite` - Replace existing entries in `dst_dir`
+    ///
+    /// # Returns
+    ///
+    /// The tasks the server started, for moves between storages
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn move_entries(
+        &self,
+        src_dir: &str,
+        dst_dir: &str,
+        names: Vec<String>,
+        overwrite: bool,
+    ) -> Result<Vec<TaskInfo>> {
+        self.move_or_copy("/api/fs/move", src_dir, dst_dir, names, overwrite)
+            .await
+    }
+
+    /// Copies entries from one remote directory into another.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_dir` - Directory holding the entries
+    /// * `dst_dir` - Directory receiving the copies
+    /// * `names` - Names of the entries in `src_dir`
+    /// * `overwrite` - Replace existing entries in `dst_dir`
+    ///
+    /// # Returns
+    ///
+    /// The tasks the server started, for copies between storages
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn copy_entries(
+        &self,
+        src_dir: &str,
+        dst_dir: &str,
+        names: Vec<String>,
+        overwrite: bool,
+    ) -> Result<Vec<TaskInfo>> {
+        self.move_or_copy("/api/fs/copy", src_dir, dst_dir, names, overwrite)
+            .await
+    }
+
+    async fn move_or_copy(
+        &self,
+        endpoint: &str,
+        src_dir: &str,
+        dst_dir: &str,
+        names: Vec<String>,
+        overwrite: bool,
+    ) -> Result<Vec<TaskInfo>> {
+        let payload = MoveCopyRequest {
+            src_dir: src_dir.to_string(),
+            dst_dir: dst_dir.to_string(),
+            names,
+            overwrite,
+        };
+        let data: Option<TasksData> = self.post_action(endpoint, &payload).await?;
+        Ok(data.unwrap_or_default().tasks)
+    }
+
+    /// Removes files or directories from one remote directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Remote directory holding the entries
+    /// * `names` - Names of the entries to remove
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn remove_entries(&self, dir: &str, names: Vec<String>) -> Result<()> {
+        debug!("Removing {:?} from {}", names, dir);
+        let payload = RemoveRequest {
+            dir: dir.to_string(),
+            names,
+        };
+        self.post_action::<_, serde::de::IgnoredAny>("/api/fs/remove", &payload)
+            .await?;
+        Ok(())
+    }
+}
@@ -4,9 +4,12 @@
 //! server, including path structure retrieval, file operations, and metadata
 //! handling.
 
+pub mod auth;
 pub mod client;
+pub mod manage;
 pub mod operations;
 pub mod rate_limiter;
+pub mod tasks;
 pub mod types;
 
 pub use client::*;
@@ -1,234 +1,384 @@
 //! High-level operations for file management and processing.
 
-use std::{fmt::Write, path::PathBuf, sync::Arc};
+use std::{fmt::Write, path::PathBuf, pin::pin};
 
 use anyhow::{Result, anyhow};
-use futures::stream::{self, StreamExt};
+use futures::{Stream, StreamExt, future};
 use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
-use reqwest::Client;
-use tokio::fs;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use tracing::{debug, info, trace, warn};
 use url::Url;
 
 use super::{
-    rate_limiter::rate_limited_request,
-    types::{
-        ApiData, ApiResponse, EntryWithPath, FileInfoRequest, is_metadata_file, is_streamable_file,
-    },
+    AlistClient,
+    types::{ApiData, ApiResponse, EntryWithPath, FileInfo, FileInfoRequest, StrmUrlMode},
 };
 use crate::{
-    get_config,
-    utils::file_ops::{download_file_with_retries, ensure_parent_dir},
+    classify::FileAction,
+    utils::file_ops::{is_local_copy_current, set_modified, write_if_changed},
 };
 
-/// Gets the raw download URL for a given file entry.
+/// Characters left unescaped in path segments, matching JavaScript's
+/// `encodeURIComponent` as used by the AList frontend
+pub(crate) const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// Builds a signed AList download URL for a remote path.
 ///
 /// # Arguments
 ///
-/// * `client` - HTTP client for making requests
-/// * `entry` - The file entry to get the URL for
+/// * `server_address` - Base address of the Alist server
+/// * `route` - `"d"` for direct downloads or `"p"` for proxied ones
+/// * `path` - Remote path of the file
+/// * `sign` - Sign from the directory listing, empty if signing is disabled
 ///
 /// # Returns
 ///
-/// The raw download URL as a string
-///
-/// # Errors
-///
-/// Returns an error if the API request fails or returns invalid data
-pub async fn get_raw_url(client: &Client, entry: &EntryWithPath) -> Result<String> {
-    trace!("file: {:?}", entry);
-    let payload = FileInfoRequest {
-        path: entry.path_str.clone(),
-        password: "".to_string(),
-        page: 1,
-        per_page: 0,
-        refresh: false,
-    };
-
-    trace!("metadata current payload:{:?}", payload);
-    let response = rate_limited_request(
-        client,
-        format!("{}/api/fs/get", get_config().server_address),
-        payload,
-    )
-    .await?;
-
-    if response.status().is_success() {
-        let api_response: ApiResponse = response.json().await?;
-        trace!("metadata api_response: {:?}", api_response);
+/// The URL in the form `{server}/{route}/{encoded path}?sign={sign}`
+pub fn build_sign_url(server_address: &str, route: &str, path: &str, sign: &str) -> String {
+    let encoded_path: String = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| format!("/{}", utf8_percent_encode(segment, PATH_SEGMENT)))
+        .collect();
+    let mut url = format!(
+        "{}/{}{}",
+        server_address.trim_end_matches('/'),
+        route,
+        encoded_path
+    );
+    if !sign.is_empty() {
+        url.push_str("?sign=");
+        url.extend(utf8_percent_encode(sign, PATH_SEGMENT));
+    }
+    url
+}
 
-        if let Some(ApiData::FileInfo(file_info)) = api_response.data {
-            let raw_url = file_info.raw_url;
-            debug!("raw_url: {}", raw_url);
-            Ok(raw_url)
+impl AlistClient {
+    /// Gets the raw download URL for a given file entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The file entry to get the URL for
+    ///
+    /// # Returns
+    ///
+    /// The raw download URL as a string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API request fails or returns invalid data
+    pub async fn get_raw_url(&self, entry: &EntryWithPath) -> Result<String> {
+        trace!("file: {:?}", entry);
+        let payload = FileInfoRequest {
+            path: entry.path_str.clone(),
+            password: "".to_string(),
+            page: 1,
+            per_page: 0,
+            refresh: false,
+        };
+
+        trace!("metadata current payload:{:?}", payload);
+        let response = self
+            .rate_limited_request(self.api_url("/api/fs/get"), payload)
+            .await?;
+
+        if response.status().is_success() {
+            let api_response: ApiResponse = response.json().await?;
+            trace!("metadata api_response: {:?}", api_response);
+
+            if let Some(ApiData::FileInfo(file_info)) = api_response.data {
+                let raw_url = file_info.raw_url;
+                debug!("raw_url: {}", raw_url);
+                Ok(raw_url)
+            } else {
+                Err(anyhow!("Invalid data"))
+            }
         } else {
-            Err(anyhow!("Invalid data"))
+            Err(anyhow!("Request failed"))
         }
-    } else {
-        Err(anyhow!("Request failed"))
     }
-}
 
-/// Copies metadata files (nfo, jpg, png, etc.) from the server to local
-/// storage.
-///
-/// # Arguments
-///
-/// * `files_with_ext` - Slice of files with their extensions
-/// * `output_path` - Local directory path where files should be saved
-/// * `m_pb` - Multi-progress bar for UI feedback
-/// * `client` - HTTP client for making requests
-///
-/// # Returns
-///
-/// Success if all metadata files were processed
-///
-/// # Errors
-///
-/// Individual file failures are logged but don't stop the overall operation
-pub async fn copy_metadata(
-    files_with_ext: &[(String, &EntryWithPath)],
-    output_path: &str,
-    m_pb: MultiProgress,
-    client: Arc<Client>,
-) -> Result<()> {
-    info!("Start to copy metadata");
-
-    let files_copy: Vec<&(String, &EntryWithPath)> = files_with_ext
-        .iter()
-        .filter(|(ext, _)| is_metadata_file(ext))
-        .collect();
-
-    let sty = ProgressStyle::with_template(
-        "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
-    )
-    .unwrap()
-    .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
-        write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
-    })
-    .progress_chars("#>-");
-
-    let pb = m_pb.add(ProgressBar::new(files_copy.len() as u64));
-    pb.set_style(sty.clone());
-    pb.enable_steady_tick(std::time::Duration::from_millis(100));
-
-    // Create a stream of futures
-    let tasks = stream::iter(files_copy.into_iter().map(|file| {
-        // Clone necessary values for the async block
-        let client = Arc::clone(&client);
-        let pb = pb.clone();
-        let m_clone = m_pb.clone();
-        let output_path = output_path.to_string();
-        async move {
-            // Construct the full local path
-            let mut local_path = PathBuf::from(&output_path);
-            let relative_p2 = file.1.path_str.trim_start_matches('/');
-            local_path.push(relative_p2);
-
-            // Obtain the raw URL asynchronously
-            let raw_url = get_raw_url(&client, file.1).await?;
-            // Attempt to download the file with retries
-            if let Err(e) = download_file_with_retries(
-                &raw_url,
-                &local_path,
-                &client,
-                file.1.entry.hash_info.clone(),
-                m_clone,
-            )
-            .await
-            {
-                warn!("Failed to download '{}': {}", raw_url, e);
-            };
+    /// Looks up a remote file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote path to look up
+    ///
+    /// # Returns
+    ///
+    /// Its information, or `None` if nothing exists at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the API request fails for another reason
+    pub async fn remote_file(&self, path: &str) -> Result<Option<FileInfo>> {
+        let payload = FileInfoRequest {
+            path: path.to_string(),
+            password: "".to_string(),
+            page: 1,
+            per_page: 0,
+            refresh: false,
+        };
+        let response = self
+            .rate_limited_request(self.api_url("/api/fs/get"), payload)
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("HTTP error: {}", response.status()));
+        }
 
-            pb.inc(1);
-            Ok(())
+        let api_response: ApiResponse = response.json().await?;
+        trace!("get api_response: {:?}", api_response);
+        match api_response.data {
+            Some(ApiData::FileInfo(file_info)) if api_response.code == 200 => Ok(Some(*file_info)),
+            _ if api_response.message.contains("not found") => Ok(None),
+            _ => Err(anyhow!(
+                "Failed to look up '{}': {}",
+                path,
+                api_response.message
+            )),
         }
-    }))
-    .buffer_unordered(get_config().concurrent_limit);
-
-    // Wait for all tasks to complete
-    tasks
-        .for_each(|res: Result<()>| async {
-            if let Err(e) = res {
-                // Optionally handle individual errors here
-                warn!("Task failed with error: {}", e);
-            }
-        })
-        .await;
+    }
 
-    info!("Metadata files created");
+    /// Gets the URL to write into a .strm file for a given entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The file entry to get the URL for
+    /// * `mode` - How the URL is obtained
+    ///
+    /// # Returns
+    ///
+    /// The streaming URL as a string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the raw URL cannot be resolved
+    pub async fn get_strm_url(&self, entry: &EntryWithPath, mode: StrmUrlMode) -> Result<String> {
+        let server_address = &self.config().server_address;
+        match mode {
+            StrmUrlMode::Raw => self.get_raw_url(entry).await,
+            StrmUrlMode::Direct => Ok(build_sign_url(
+                server_address,
+                "d",
+                &entry.path_str,
+                &entry.entry.sign,
+            )),
+            StrmUrlMode::Proxy => Ok(build_sign_url(
+                server_address,
+                "p",
+                &entry.path_str,
+                &entry.entry.sign,
+            )),
+        }
+    }
 
-    Ok(())
-}
+    /// Copies metadata files (nfo, jpg, png, etc.) from the server to local
+    /// storage.
+    ///
+    /// Files are downloaded as they arrive, so this can consume
+    /// [`AlistClient::stream_path_structure`] while the scan is running.
+    /// Downloaded files take the `modified` time of their remote file, and
+    /// local copies with the remote size and time are not fetched again.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Stream of remote entries, those not classified as
+    ///   [`FileAction::Copy`] are skipped
+    /// * `output_path` - Local directory path where files should be saved
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The local files that were downloaded, without those already current
+    ///
+    /// # Errors
+    ///
+    /// Individual file failures are logged but don't stop the overall operation
+    pub async fn copy_metadata(
+        &self,
+        files: impl Stream<Item = EntryWithPath>,
+        output_path: &str,
+        m_pb: MultiProgress,
+    ) -> Result<Vec<PathBuf>> {
+        info!("Start to copy metadata");
 
-/// Creates .strm files for streamable media files.
-///
-/// .strm files contain URLs that media players can use to stream content
-/// directly from the Alist server without downloading the entire file.
-///
-/// # Arguments
-///
-/// * `files_with_ext` - Slice of files with their extensions
-/// * `output_path` - Local directory path where .strm files should be created
-/// * `m_pb` - Multi-progress bar for UI feedback
-/// * `client` - HTTP client for making requests
-///
-/// # Returns
-///
-/// Success if all .strm files were created
-///
-/// # Errors
-///
-/// Returns an error if file system operations fail
-pub async fn create_strm_file(
-    files_with_ext: &[(String, &EntryWithPath)],
-    output_path: &str,
-    m_pb: MultiProgress,
-    client: Arc<Client>,
-) -> Result<()> {
-    let files_strm = files_with_ext
-        .iter()
-        .filter(|(ext, _)| is_streamable_file(ext));
-
-    let pb = m_pb.add(ProgressBar::new(files_strm.clone().count() as u64));
-    pb.set_style(
-        ProgressStyle::with_template(
+        let sty = ProgressStyle::with_template(
             "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
         )
         .unwrap()
         .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
             write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
         })
-        .progress_chars("#>-"),
-    );
-    pb.enable_steady_tick(std::time::Duration::from_millis(100));
-
-    info!("Start to create strm files");
-    let mut results = stream::iter(files_strm.map(|f| {
-        let client_ref = &client;
-        async move {
-            let raw_url = get_raw_url(client_ref, f.1).await?;
-            let mut local_path = PathBuf::from(output_path);
-            let relative_p2 = f.1.path_str.trim_start_matches('/');
-            local_path.push(relative_p2);
-            local_path.set_extension("strm");
-
-            let parsed_url = Url::parse(&raw_url)
-                .map_err(|e| anyhow!("Failed to parse URL '{}': {}", raw_url, e))?;
-            Ok::<(Url, PathBuf), anyhow::Error>((parsed_url, local_path))
-        }
-    }))
-    .buffer_unordered(get_config().concurrent_limit);
+        .progress_chars("#>-");
+
+        let pb = m_pb.add(ProgressBar::new(0));
+        pb.set_style(sty.clone());
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+        // Create a stream of futures
+        let tasks = files
+            .filter(|file| {
+                future::ready(self.config().classify(&file.path_str) == Some(FileAction::Copy))
+            })
+            .map(|file| {
+                pb.inc_length(1);
+                // Clone necessary values for the async block
+                let pb = pb.clone();
+                let m_clone = m_pb.clone();
+                let output_path = output_path.to_string();
+                async move {
+                    // Construct the full local path
+                    let mut local_path = PathBuf::from(&output_path);
+                    let relative_p2 = file.path_str.trim_start_matches('/');
+                    local_path.push(relative_p2);
 
-    while let Some(result) = results.next().await {
-        let (raw_url, local_path) = result?;
+                    // Nothing to fetch if the local copy has the remote size and time
+                    if is_local_copy_current(&local_path, &file.entry).await {
+                        trace!("Metadata is current: {}", local_path.display());
+                        pb.inc(1);
+                        return Ok(None);
+                    }
 
-        ensure_parent_dir(&local_path).await?;
-        fs::write(&local_path, raw_url.as_str()).await?;
-        pb.inc(1);
+                    // Obtain the raw URL asynchronously
+                    let raw_url = self.get_raw_url(&file).await?;
+                    // Attempt to download the file with retries
+                    let result = self
+                        .download_file_with_retries(
+                            &raw_url,
+                            &local_path,
+                            file.entry.hash_info.clone(),
+                            file.entry.size,
+                            m_clone,
+                        )
+                        .await;
+
+                    pb.inc(1);
+                    result.map_err(|e| anyhow!("Failed to download '{}': {}", raw_url, e))?;
+                    // Lets the next run recognize the copy as current
+                    if let Err(e) = set_modified(&local_path, &file.entry.modified).await {
+                        debug!("Kept mtime of {}: {}", local_path.display(), e);
+                    }
+                    Ok(Some(local_path))
+                }
+            })
+            .buffer_unordered(self.config().concurrent_limit);
+
+        // Wait for all tasks to complete
+        let copied = tasks
+            .filter_map(|res: Result<Option<PathBuf>>| async {
+                match res {
+                    Ok(local_path) => local_path,
+                    Err(e) => {
+                        warn!("Task failed with error: {}", e);
+                        None
+                    }
+                }
+            })
+            .collect()
+            .await;
+
+        info!("Metadata files created");
+
+        Ok(copied)
     }
 
-    info!("strm file created");
+    /// Creates .strm files for streamable media files.
+    ///
+    /// .strm files contain URLs that media players can use to stream content
+    /// directly from the Alist server without downloading the entire file.
+    ///
+    /// Like [`AlistClient::copy_metadata`], files are processed as they arrive.
+    /// Existing .strm files are only replaced if their URL changed, and every
+    /// .strm file takes the `modified` time of its remote file.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Stream of remote entries, those not classified as
+    ///   [`FileAction::Strm`] are skipped
+    /// * `output_path` - Local directory path where .strm files should be
+    ///   created
+    /// * `url_mode` - How the URL inside each .strm file is obtained
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The .strm files that were created or whose URL changed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if file system operations fail
+    pub async fn create_strm_file(
+        &self,
+        files: impl Stream<Item = EntryWithPath>,
+        output_path: &str,
+        url_mode: StrmUrlMode,
+        m_pb: MultiProgress,
+    ) -> Result<Vec<PathBuf>> {
+        let pb = m_pb.add(ProgressBar::new(0));
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
+            )
+            .unwrap()
+            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
+                write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
+            })
+            .progress_chars("#>-"),
+        );
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+        info!("Start to create strm files");
+        let results = files
+            .filter(|f| {
+                future::ready(self.config().classify(&f.path_str) == Some(FileAction::Strm))
+            })
+            .map(|f| {
+                pb.inc_length(1);
+                async move {
+                    let raw_url = self.get_strm_url(&f, url_mode).await?;
+                    let mut local_path = PathBuf::from(output_path);
+                    let relative_p2 = f.path_str.trim_start_matches('/');
+                    local_path.push(relative_p2);
+                    local_path.set_extension("strm");
+
+                    let parsed_url = Url::parse(&raw_url)
+                        .map_err(|e| anyhow!("Failed to parse URL '{}': {}", raw_url, e))?;
+                    Ok::<(Url, PathBuf, String), anyhow::Error>((
+                        parsed_url,
+                        local_path,
+                        f.entry.modified,
+                    ))
+                }
+            })
+            .buffer_unordered(self.config().concurrent_limit);
+        let mut results = pin!(results);
+        let mut written = Vec::new();
+
+        while let Some(result) = results.next().await {
+            let (raw_url, local_path, modified) = result?;
 
-    Ok(())
+            // Rewriting an unchanged file would make media servers probe it again
+            let changed = write_if_changed(&local_path, raw_url.as_str().as_bytes()).await?;
+            if let Err(e) = set_modified(&local_path, &modified).await {
+                debug!("Kept mtime of {}: {}", local_path.display(), e);
+            }
+            if changed {
+                written.push(local_path);
+            }
+            pb.inc(1);
+        }
+
+        info!("strm file created");
+
+        Ok(written)
+    }
 }
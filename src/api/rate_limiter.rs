@@ -1,103 +1,212 @@
 //! Rate limiting functionality for API requests.
 
-use std::{num::NonZeroU32, sync::LazyLock, time::Duration};
+use std::{num::NonZeroU32, time::Duration};
 
 use anyhow::{Result, anyhow};
-use governor::{
-    Quota, RateLimiter,
-    clock::DefaultClock,
-    state::{InMemoryState, NotKeyed},
-};
-use reqwest::Client;
-
-use crate::get_config;
-
-/// Global rate limiter instance
-static TPS_RATE_LIMITER: LazyLock<RateLimiter<NotKeyed, InMemoryState, DefaultClock>> =
-    LazyLock::new(|| {
-        let quota = Quota::per_second(
-            NonZeroU32::new(get_config().tpslimit).unwrap_or_else(|| NonZeroU32::new(1).unwrap()),
-        );
-        RateLimiter::direct(quota)
-    });
-
-/// Performs a rate-limited POST request with JSON payload.
+use bytes::Bytes;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use reqwest::{Method, StatusCode, header::HeaderMap};
+
+use super::{AlistClient, auth};
+
+/// Creates a rate limiter allowing `tpslimit` requests per second
 ///
 /// # Arguments
 ///
-/// * `client` - The HTTP client to use for the request
-/// * `url` - The URL to send the request to
-/// * `payload` - The payload to serialize as JSON
-///
-/// # Returns
-///
-/// The HTTP response if successful
-///
-/// # Errors
-///
-/// Returns an error if the rate limiter times out or the request fails
-pub async fn rate_limited_request<T>(
-    client: &Client,
-    url: String,
-    payload: T,
-) -> Result<reqwest::Response>
-where
-    T: serde::Serialize,
-{
-    let timeout_secs = get_config().timeout;
-
-    // Wait until we're allowed to make a request
-    tokio::time::timeout(
-        Duration::from_secs(timeout_secs),
-        TPS_RATE_LIMITER.until_ready(),
-    )
-    .await
-    .map_err(|_| anyhow!("Rate limiter timeout"))?;
-
-    // Now make the request
-    let response = client
-        .post(url)
-        .timeout(Duration::from_secs(timeout_secs))
-        .json(&payload)
-        .header("Authorization", &get_config().token)
-        .header("Content-Type", "application/json")
-        .send()
-        .await?;
-
-    Ok(response)
+/// * `tpslimit` - Requests per second, `0` is treated as `1`
+pub(crate) fn new_rate_limiter(tpslimit: u32) -> DefaultDirectRateLimiter {
+    let quota =
+        Quota::per_second(NonZeroU32::new(tpslimit).unwrap_or_else(|| NonZeroU32::new(1).unwrap()));
+    RateLimiter::direct(quota)
 }
 
-/// Performs a rate-limited GET request.
-///
-/// # Arguments
-///
-/// * `client` - The HTTP client to use for the request
-/// * `url` - The URL to send the request to
-///
-/// # Returns
-///
-/// The HTTP response if successful
-///
-/// # Errors
-///
-/// Returns an error if the rate limiter times out or the request fails
-pub async fn rate_limited_get(client: &Client, url: &str) -> Result<reqwest::Response> {
-    let timeout_secs = get_config().timeout;
-
-    // Wait until we're allowed to make a request
-    tokio::time::timeout(
-        Duration::from_secs(timeout_secs),
-        TPS_RATE_LIMITER.until_ready(),
-    )
-    .await
-    .map_err(|_| anyhow!("Rate limiter timeout"))?;
-
-    // Now make the request
-    let response = client
-        .get(url)
-        .timeout(Duration::from_secs(timeout_secs))
-        .send()
-        .await?;
-
-    Ok(response)
+impl AlistClient {
+    /// Waits until the rate limiter allows another request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no permit is available within the API timeout
+    pub async fn wait_for_permit(&self) -> Result<()> {
+        tokio::time::timeout(
+            Duration::from_secs(self.config().timeout),
+            self.rate_limiter().until_ready(),
+        )
+        .await
+        .map_err(|_| anyhow!("Rate limiter timeout"))
+    }
+
+    /// Sends one authenticated API request and buffers the response.
+    async fn send_api<T>(
+        &self,
+        method: Method,
+        url: &str,
+        payload: Option<&T>,
+        token: &str,
+    ) -> Result<(StatusCode, HeaderMap, Bytes)>
+    where
+        T: serde::Serialize,
+    {
+        // Wait until we're allowed to make a request
+        self.wait_for_permit().await?;
+
+        // Now make the request
+        let mut request = self
+            .http()
+            .request(method, url)
+            .timeout(Duration::from_secs(self.config().timeout))
+            .header("Authorization", token);
+        if let Some(payload) = payload {
+            request = request
+                .json(payload)
+                .header("Content-Type", "application/json");
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        Ok((status, headers, body))
+    }
+
+    /// Sends an API request, logging in again and retrying once if the
+    /// server rejects the token and credentials are configured.
+    async fn authorized_request<T>(
+        &self,
+        method: Method,
+        url: &str,
+        payload: Option<&T>,
+    ) -> Result<reqwest::Response>
+    where
+        T: serde::Serialize,
+    {
+        let token = self.current_token().await;
+        let mut result = self.send_api(method.clone(), url, payload, &token).await?;
+
+        if self.can_refresh() && auth::is_auth_failure(result.0, &result.2) {
+            self.refresh_token(&token).await?;
+            result = self
+                .send_api(method, url, payload, &self.current_token().await)
+                .await?;
+        }
+
+        // Hand the buffered body back as a regular response
+        let (status, headers, body) = result;
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        Ok(reqwest::Response::from(response))
+    }
+
+    /// Performs a rate-limited POST request with JSON payload.
+    ///
+    /// If the server rejects the token and credentials are configured, logs in
+    /// again and retries the request once.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to
+    /// * `payload` - The payload to serialize as JSON
+    ///
+    /// # Returns
+    ///
+    /// The HTTP response if successful
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rate limiter times out, re-authentication fails
+    /// or the request fails
+    pub async fn rate_limited_request<T>(
+        &self,
+        url: String,
+        payload: T,
+    ) -> Result<reqwest::Response>
+    where
+        T: serde::Serialize,
+    {
+        self.authorized_request(Method::POST, &url, Some(&payload))
+            .await
+    }
+
+    /// Performs a rate-limited GET request to an API endpoint, retrying once
+    /// after logging in again like [`AlistClient::rate_limited_request`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to
+    ///
+    /// # Returns
+    ///
+    /// The HTTP response if successful
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rate limiter times out, re-authentication fails
+    /// or the request fails
+    pub async fn rate_limited_api_get(&self, url: &str) -> Result<reqwest::Response> {
+        self.authorized_request::<()>(Method::GET, url, None).await
+    }
+
+    /// Performs a rate-limited GET request for a file download.
+    ///
+    /// Unlike [`AlistClient::rate_limited_request`] there is no total timeout,
+    /// since streaming a large file may take arbitrarily long. Connecting is
+    /// bounded by the HTTP client's connect timeout and the wait for the
+    /// response headers by the read timeout; callers bound the wait for each
+    /// body chunk with [`AlistClient::read_chunk`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to
+    /// * `headers` - Extra request headers, e.g. `Range` when resuming
+    ///
+    /// # Returns
+    ///
+    /// The HTTP response if successful
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rate limiter, the connection or the response
+    /// times out, or the request fails
+    pub async fn rate_limited_get(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response> {
+        let read_timeout = self.config().read_timeout;
+
+        // Wait until we're allowed to make a request
+        self.wait_for_permit().await?;
+
+        // Now make the request
+        let response = tokio::time::timeout(
+            Duration::from_secs(read_timeout),
+            self.http().get(url).headers(headers).send(),
+        )
+        .await
+        .map_err(|_| anyhow!("No response received for {}s", read_timeout))??;
+
+        Ok(response)
+    }
+
+    /// Reads the next body chunk of a download, bounded by the idle timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response being streamed
+    ///
+    /// # Returns
+    ///
+    /// The next chunk, or `None` at the end of the body
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no data arrives within the read timeout or the
+    /// connection fails
+    pub async fn read_chunk(&self, response: &mut reqwest::Response) -> Result<Option<Bytes>> {
+        let read_timeout = self.config().read_timeout;
+        let chunk = tokio::time::timeout(Duration::from_secs(read_timeout), response.chunk())
+            .await
+            .map_err(|_| anyhow!("No data received for {}s", read_timeout))??;
+        Ok(chunk)
+    }
 }
@@ -0,0 +1,549 @@
+//! Background tasks on the server: uploads sent with `As-Task`, copies and
+//! moves between storages, and offline downloads.
+//!
+//! Each kind of work has its own queue under `/api/task/<kind>/`. Tasks are
+//! polled, so waiting for one costs a request per task every interval.
+
+use std::{collections::HashMap, fmt, io::Write, time::Duration};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{Method, StatusCode};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use tracing::{debug, warn};
+
+use super::{AlistClient, types::TaskInfo};
+
+/// Task queues of the server, one per kind of work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum TaskKind {
+    /// Uploads sent with `As-Task`
+    Upload,
+    /// Copies between storages
+    Copy,
+    /// Moves between storages
+    Move,
+    /// Offline downloads into the server's temporary directory
+    OfflineDownload,
+    /// Transfers of finished offline downloads to their storage
+    OfflineDownloadTransfer,
+    /// Archive extraction
+    Decompress,
+    /// Uploads of extracted files
+    DecompressUpload,
+}
+
+impl TaskKind {
+    /// Every queue, in the order they are listed
+    pub const ALL: [TaskKind; 7] = [
+        TaskKind::Upload,
+        TaskKind::Copy,
+        TaskKind::Move,
+        TaskKind::OfflineDownload,
+        TaskKind::OfflineDownloadTransfer,
+        TaskKind::Decompress,
+        TaskKind::DecompressUpload,
+    ];
+
+    /// Returns the path segment of the queue under `/api/task/`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskKind::Upload => "upload",
+            TaskKind::Copy => "copy",
+            TaskKind::Move => "move",
+            TaskKind::OfflineDownload => "offline_download",
+            TaskKind::OfflineDownloadTransfer => "offline_download_transfer",
+            TaskKind::Decompress => "decompress",
+            TaskKind::DecompressUpload => "decompress_upload",
+        }
+    }
+}
+
+impl fmt::Display for TaskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// State of a task, as numbered by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Pending,
+    Running,
+    Succeeded,
+    Canceling,
+    Canceled,
+    Errored,
+    Failing,
+    Failed,
+    WaitingRetry,
+    BeforeRetry,
+    /// A state this client does not know
+    Other(u32),
+}
+
+impl From<u32> for TaskState {
+    fn from(state: u32) -> Self {
+        match state {
+            0 => TaskState::Pending,
+            1 => TaskState::Running,
+            2 => TaskState::Succeeded,
+            3 => TaskState::Canceling,
+            4 => TaskState::Canceled,
+            5 => TaskState::Errored,
+            6 => TaskState::Failing,
+            7 => TaskState::Failed,
+            8 => TaskState::WaitingRetry,
+            9 => TaskState::BeforeRetry,
+            other => TaskState::Other(other),
+        }
+    }
+}
+
+impl TaskState {
+    /// Whether the task has stopped for good; errored tasks may still be
+    /// retried by the server
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            TaskState::Succeeded | TaskState::Canceled | TaskState::Failed
+        )
+    }
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskState::Pending => f.write_str("pending"),
+            TaskState::Running => f.write_str("running"),
+            TaskState::Succeeded => f.write_str("succeeded"),
+            TaskState::Canceling => f.write_str("canceling"),
+            TaskState::Canceled => f.write_str("canceled"),
+            TaskState::Errored => f.write_str("errored"),
+            TaskState::Failing => f.write_str("failing"),
+            TaskState::Failed => f.write_str("failed"),
+            TaskState::WaitingRetry => f.write_str("waiting retry"),
+            TaskState::BeforeRetry => f.write_str("before retry"),
+            TaskState::Other(state) => write!(f, "state {state}"),
+        }
+    }
+}
+
+/// Error for a task queue the server does not have, such as moves on older
+/// versions
+#[derive(Debug)]
+pub struct NoTaskQueue(pub TaskKind);
+
+impl fmt::Display for NoTaskQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The server has no {} task queue", self.0)
+    }
+}
+
+impl std::error::Error for NoTaskQueue {}
+
+impl TaskInfo {
+    /// Returns the state of the task
+    pub fn task_state(&self) -> TaskState {
+        self.state.into()
+    }
+}
+
+/// Writes a table of tasks
+///
+/// # Arguments
+///
+/// * `out` - Where to write the table
+/// * `tasks` - The tasks with their queue
+///
+/// # Errors
+///
+/// Returns an error if writing fails
+pub fn write_task_table(out: &mut impl Write, tasks: &[(TaskKind, TaskInfo)]) -> Result<()> {
+    let kind_width = tasks
+        .iter()
+        .map(|(kind, _)| kind.as_str().len())
+        .max()
+        .unwrap_or(0)
+        .max("KIND".len());
+    let id_width = tasks
+        .iter()
+        .map(|(_, task)| task.id.len())
+        .max()
+        .unwrap_or(0)
+        .max("ID".len());
+    writeln!(
+        out,
+        "{:<kind_width$}  {:<id_width$}  {:<13}  {:>8}  NAME",
+        "KIND", "ID", "STATE", "PROGRESS"
+    )?;
+    for (kind, task) in tasks {
+        writeln!(
+            out,
+            "{:<kind_width$}  {:<id_width$}  {:<13}  {:>7.1}%  {}",
+            kind.as_str(),
+            task.id,
+            task.task_state().to_string(),
+            task.progress,
+            task.name
+        )?;
+        if !task.error.is_empty() {
+            writeln!(
+                out,
+                "{:<kind_width$}  {:<id_width$}  error: {}",
+                "", "", task.error
+            )?;
+        }
+    }
+    if tasks.is_empty() {
+        writeln!(out, "No tasks")?;
+    }
+    Ok(())
+}
+
+/// Adds a progress bar showing one task
+fn task_bar(m_pb: &MultiProgress, kind: TaskKind, task: &TaskInfo) -> ProgressBar {
+    let pb = m_pb.add(ProgressBar::new(100));
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>3}% {wide_msg}",
+        )
+        .unwrap()
+        .progress_chars("#>-"),
+    );
+    pb.enable_steady_tick(Duration::from_millis(100));
+    update_task_bar(&pb, kind, task);
+    pb
+}
+
+/// Shows the latest progress of a task, finishing the bar once it stopped
+fn update_task_bar(pb: &ProgressBar, kind: TaskKind, task: &TaskInfo) {
+    pb.set_position(task.progress.clamp(0.0, 100.0) as u64);
+    let mut message = format!("{} {} ({})", kind, task.name, task.task_state());
+    if !task.error.is_empty() {
+        message.push_str(": ");
+        message.push_str(&task.error);
+    }
+    if task.task_state().is_finished() {
+        pb.finish_with_message(message);
+    } else {
+        pb.set_message(message);
+    }
+}
+
+impl AlistClient {
+    /// Sends a request to a task queue and checks the response
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - `GET` for the listings, `POST` for everything else
+    /// * `kind` - The queue
+    /// * `action` - Last path segment of the endpoint, e.g. `cancel`
+    /// * `id` - The task the action applies to, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`NoTaskQueue`] error if the server has no such queue, or an
+    /// error if the request fails or the server rejects it
+    async fn task_request<D>(
+        &self,
+        method: Method,
+        kind: TaskKind,
+        action: &str,
+        id: Option<&str>,
+    ) -> Result<Option<D>>
+    where
+        D: DeserializeOwned + fmt::Debug,
+    {
+        let endpoint = match id {
+            Some(id) => format!(
+                "/api/task/{}/{}?tid={}",
+                kind,
+                action,
+                utf8_percent_encode(id, NON_ALPHANUMERIC)
+            ),
+            None => format!("/api/task/{kind}/{action}"),
+        };
+        let url = self.api_url(&endpoint);
+        let response = if method == Method::GET {
+            self.rate_limited_api_get(&url).await?
+        } else {
+            self.rate_limited_request(url, ()).await?
+        };
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(NoTaskQueue(kind).into());
+        }
+        Self::parse_action_response(&endpoint, response).await
+    }
+
+    /// Lists the tasks of one queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The queue
+    /// * `done` - List finished tasks instead of pending and running ones
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn list_tasks(&self, kind: TaskKind, done: bool) -> Result<Vec<TaskInfo>> {
+        let action = if done { "done" } else { "undone" };
+        let tasks: Option<Vec<TaskInfo>> =
+            self.task_request(Method::GET, kind, action, None).await?;
+        Ok(tasks.unwrap_or_default())
+    }
+
+    /// Lists the tasks of several queues
+    ///
+    /// Queues the server does not have are skipped, other failures are
+    /// logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `kinds` - The queues
+    /// * `done` - List finished tasks instead of pending and running ones
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no queue could be listed
+    pub async fn list_tasks_of(
+        &self,
+        kinds: &[TaskKind],
+        done: bool,
+    ) -> Result<Vec<(TaskKind, TaskInfo)>> {
+        let mut tasks = Vec::new();
+        let mut listed = 0;
+        let mut last_error = None;
+        for &kind in kinds {
+            match self.list_tasks(kind, done).await {
+                Ok(found) => {
+                    listed += 1;
+                    tasks.extend(found.into_iter().map(|task| (kind, task)));
+                }
+                Err(e) => {
+                    if e.is::<NoTaskQueue>() {
+                        debug!("{}", e);
+                    } else {
+                        warn!("Failed to list {} tasks: {}", kind, e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if listed == 0 => Err(e),
+            _ => Ok(tasks),
+        }
+    }
+
+    /// Returns the current state of a task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the task does not exist
+    pub async fn task_info(&self, kind: TaskKind, id: &str) -> Result<TaskInfo> {
+        self.task_request(Method::POST, kind, "info", Some(id))
+            .await?
+            .ok_or_else(|| anyhow!("No {} task with id {}", kind, id))
+    }
+
+    /// Cancels a pending or running task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn cancel_task(&self, kind: TaskKind, id: &str) -> Result<()> {
+        self.task_request::<IgnoredAny>(Method::POST, kind, "cancel", Some(id))
+            .await?;
+        Ok(())
+    }
+
+    /// Runs a failed or canceled task again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn retry_task(&self, kind: TaskKind, id: &str) -> Result<()> {
+        self.task_request::<IgnoredAny>(Method::POST, kind, "retry", Some(id))
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the finished tasks of a queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server rejects it
+    pub async fn clear_done_tasks(&self, kind: TaskKind) -> Result<()> {
+        self.task_request::<IgnoredAny>(Method::POST, kind, "clear_done", None)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the finished tasks of several queues, skipping queues the
+    /// server does not have
+    ///
+    /// # Returns
+    ///
+    /// The number of queues that were cleared
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no queue could be cleared
+    pub async fn clear_done_tasks_of(&self, kinds: &[TaskKind]) -> Result<usize> {
+        let mut cleared = 0;
+        let mut last_error = None;
+        for &kind in kinds {
+            match self.clear_done_tasks(kind).await {
+                Ok(()) => cleared += 1,
+                Err(e) => {
+                    if e.is::<NoTaskQueue>() {
+                        debug!("{}", e);
+                    } else {
+                        warn!("Failed to clear {} tasks: {}", kind, e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if cleared == 0 => Err(e),
+            _ => Ok(cleared),
+        }
+    }
+
+    /// Waits until tasks of one queue have finished, showing a progress bar
+    /// for each
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The queue the tasks were submitted to
+    /// * `ids` - The tasks to wait for
+    /// * `interval` - Time between polls
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The final state of each task, in the order of `ids`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a task cannot be looked up, e.g. because it was
+    /// cleared while waiting
+    pub async fn wait_for_tasks(
+        &self,
+        kind: TaskKind,
+        ids: &[String],
+        interval: Duration,
+        m_pb: MultiProgress,
+    ) -> Result<Vec<TaskInfo>> {
+        let mut finished: Vec<Option<TaskInfo>> = vec![None; ids.len()];
+        let mut bars: Vec<Option<ProgressBar>> = vec![None; ids.len()];
+        loop {
+            for (index, id) in ids.iter().enumerate() {
+                if finished[index].is_some() {
+                    continue;
+                }
+                let task = self.task_info(kind, id).await?;
+                match &bars[index] {
+                    Some(pb) => update_task_bar(pb, kind, &task),
+                    None => bars[index] = Some(task_bar(&m_pb, kind, &task)),
+                }
+                if task.task_state().is_finished() {
+                    debug!("{} task {} {}", kind, id, task.task_state());
+                    finished[index] = Some(task);
+                }
+            }
+            if finished.iter().all(Option::is_some) {
+                return Ok(finished.into_iter().flatten().collect());
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Waits until a task has finished, see [`AlistClient::wait_for_tasks`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the task cannot be looked up
+    pub async fn wait_for_task(
+        &self,
+        kind: TaskKind,
+        id: &str,
+        interval: Duration,
+        m_pb: MultiProgress,
+    ) -> Result<TaskInfo> {
+        self.wait_for_tasks(kind, &[id.to_string()], interval, m_pb)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("No {} task with id {}", kind, id))
+    }
+
+    /// Shows live progress of the pending and running tasks of several
+    /// queues until none are left, picking up tasks submitted meanwhile
+    ///
+    /// # Arguments
+    ///
+    /// * `kinds` - The queues to watch
+    /// * `interval` - Time between polls
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The final state of the tasks that finished while watching
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no queue could be listed
+    pub async fn watch_tasks(
+        &self,
+        kinds: &[TaskKind],
+        interval: Duration,
+        m_pb: MultiProgress,
+    ) -> Result<Vec<(TaskKind, TaskInfo)>> {
+        let mut bars: HashMap<(TaskKind, String), (ProgressBar, TaskInfo)> = HashMap::new();
+        let mut finished = Vec::new();
+        loop {
+            let running = self.list_tasks_of(kinds, false).await?;
+            let mut seen = Vec::with_capacity(running.len());
+            for (kind, task) in running {
+                let key = (kind, task.id.clone());
+                match bars.get_mut(&key) {
+                    Some((pb, last)) => {
+                        update_task_bar(pb, kind, &task);
+                        *last = task;
+                    }
+                    None => {
+                        let pb = task_bar(&m_pb, kind, &task);
+                        bars.insert(key.clone(), (pb, task));
+                    }
+                }
+                seen.push(key);
+            }
+
+            // Tasks that left the list have finished, look up how
+            let gone: Vec<_> = bars
+                .keys()
+                .filter(|key| !seen.contains(key))
+                .cloned()
+                .collect();
+            for key in gone {
+                let (pb, last) = bars.remove(&key).unwrap();
+                let (kind, id) = key;
+                let task = self.task_info(kind, &id).await.unwrap_or(last);
+                update_task_bar(&pb, kind, &task);
+                if !pb.is_finished() {
+                    pb.finish();
+                }
+                finished.push((kind, task));
+            }
+
+            if bars.is_empty() {
+                return Ok(finished);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
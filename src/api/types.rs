@@ -1,21 +1,34 @@
 //! Data types and structures for Alist API communication.
 
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// File extensions that should be converted to .strm files
-pub const FILE_STRM: [&str; 14] = [
+pub const FILE_STRM: [&str; 18] = [
     "mkv", "iso", "ts", "mp4", "avi", "rmvb", "wmv", "m2ts", "mpg", "flv", "rm", "mov", "wav",
-    "mp3",
+    "mp3", "flac", "webm", "m4v", "m4a",
 ];
 
 /// Metadata file extensions to copy alongside media files
-const META_SUFF: [&str; 9] = [
-    "nfo", "jpg", "png", "svg", "ass", "srt", "sup", "vtt", "txt",
+pub const META_SUFF: [&str; 13] = [
+    "nfo", "jpg", "png", "svg", "ass", "srt", "sup", "vtt", "txt", "ssa", "jpeg", "webp", "bdmv",
 ];
 
+/// How the URL written into a .strm file is obtained
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum StrmUrlMode {
+    /// Resolve the storage's raw URL with one `/api/fs/get` call per file
+    #[default]
+    Raw,
+    /// Compose an AList `/d/` download URL from the listing's `sign`
+    Direct,
+    /// Compose an AList `/p/` proxy URL from the listing's `sign`
+    Proxy,
+}
+
 /// Hash information for file verification
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum HashObject {
     Sha1 { sha1: String },
@@ -110,14 +123,198 @@ pub struct ApiResponse {
     pub data: Option<ApiData>,
 }
 
+/// Request payload for `/api/auth/login` and `/api/auth/login/hash`
+#[derive(Serialize, Debug)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+    pub otp_code: String,
+}
+
+/// Token returned by a successful login
+#[derive(Deserialize, Debug)]
+pub struct LoginData {
+    pub token: String,
+}
+
+/// Response of the login endpoints
+#[derive(Deserialize, Debug)]
+pub struct LoginResponse {
+    pub code: u32,
+    pub message: String,
+    pub data: Option<LoginData>,
+}
+
+/// Request payload for `/api/fs/mkdir`
+#[derive(Serialize, Debug)]
+pub struct MkdirRequest {
+    pub path: String,
+}
+
+/// Request payload for `/api/fs/rename`
+#[derive(Serialize, Debug)]
+pub struct RenameRequest {
+    /// Full path of the entry to rename
+    pub path: String,
+    /// New name, without a directory
+    pub name: String,
+    /// Replace an existing entry with the new name
+    pub overwrite: bool,
+}
+
+/// One entry renamed by `/api/fs/batch_rename`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RenameObject {
+    pub src_name: String,
+    pub new_name: String,
+}
+
+/// Request payload for `/api/fs/batch_rename`
+#[derive(Serialize, Debug)]
+pub struct BatchRenameRequest {
+    /// Directory holding the entries
+    pub src_dir: String,
+    pub rename_objects: Vec<RenameObject>,
+}
+
+/// Request payload for `/api/fs/regex_rename`
+#[derive(Serialize, Debug)]
+pub struct RegexRenameRequest {
+    /// Directory whose entries are renamed
+    pub src_dir: String,
+    /// Pattern the names must match
+    pub src_name_regex: String,
+    /// Replacement, `$1` or `${name}` refer to capture groups
+    pub new_name_regex: String,
+}
+
+/// Request payload for `/api/fs/move` and `/api/fs/copy`
+#[derive(Serialize, Debug)]
+pub struct MoveCopyRequest {
+    pub src_dir: String,
+    pub dst_dir: String,
+    /// Names of the entries in `src_dir`
+    pub names: Vec<String>,
+    /// Replace existing entries in `dst_dir`
+    pub overwrite: bool,
+}
+
+/// Request payload for `/api/fs/remove`
+#[derive(Serialize, Debug)]
+pub struct RemoveRequest {
+    /// Directory holding the entries
+    pub dir: String,
+    /// Names of the files and directories to remove
+    pub names: Vec<String>,
+}
+
+/// Background task on the server, such as an upload sent with `As-Task`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskInfo {
+    pub id: String,
+    pub name: String,
+    /// Numeric state: pending, running, succeeded, failed, ...
+    #[serde(default)]
+    pub state: u32,
+    /// Human readable status
+    #[serde(default)]
+    pub status: String,
+    /// Percent done
+    #[serde(default)]
+    pub progress: f64,
+    /// Size of the data the task transfers, `0` if unknown
+    #[serde(default)]
+    pub total_bytes: u64,
+    #[serde(default)]
+    pub error: String,
+}
+
+/// Data returned by the upload endpoints
+#[derive(Deserialize, Debug)]
+pub struct UploadData {
+    /// The server-side transfer, if the upload was sent as a task
+    pub task: Option<TaskInfo>,
+}
+
+/// Response of `/api/fs/put` and `/api/fs/form`
+#[derive(Deserialize, Debug)]
+pub struct UploadResponse {
+    pub code: u32,
+    pub message: String,
+    pub data: Option<UploadData>,
+}
+
+/// Tasks started by a copy or move between storages
+#[derive(Deserialize, Debug, Default)]
+pub struct TasksData {
+    #[serde(default)]
+    pub tasks: Vec<TaskInfo>,
+}
+
+/// Response of the endpoints that change files
+#[derive(Deserialize, Debug)]
+pub struct ActionResponse<T> {
+    pub code: u32,
+    pub message: String,
+    pub data: Option<T>,
+}
+
 /// Entry combined with its full path information
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EntryWithPath {
     pub entry: EntryInfo,
     pub path_str: String,
     pub provider: String,
 }
 
+/// Result of traversing a remote directory tree
+#[derive(Debug, Default)]
+pub struct PathStructure {
+    /// Every file and directory that was listed successfully
+    pub entries: Vec<EntryWithPath>,
+    /// Directories whose listing failed after all retries; their contents
+    /// are unknown and must not be treated as deleted
+    pub failed_dirs: Vec<String>,
+}
+
+/// Error yielded by the traversal stream for a directory that could not be
+/// listed after all retries
+#[derive(Debug)]
+pub struct ListDirError {
+    /// Remote path of the directory
+    pub path: String,
+    /// Last error returned while listing it
+    pub source: anyhow::Error,
+}
+
+impl std::fmt::Display for ListDirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to list directory '{}': {}",
+            self.path, self.source
+        )
+    }
+}
+
+impl std::error::Error for ListDirError {}
+
+/// Checks if `path` is `dir` itself or lies beneath it
+///
+/// # Arguments
+///
+/// * `path` - The remote path to check
+/// * `dir` - The remote directory
+///
+/// # Returns
+///
+/// `true` if `path` is inside `dir`
+pub fn is_path_within(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
 /// Checks if metadata should be copied based on file extension
 ///
 /// # Arguments
@@ -126,9 +323,11 @@ pub struct EntryWithPath {
 ///
 /// # Returns
 ///
-/// `true` if the extension is in the metadata suffix list
+/// `true` if the extension is in the metadata suffix list, ignoring case
 pub fn is_metadata_file(extension: &str) -> bool {
-    META_SUFF.contains(&extension)
+    META_SUFF
+        .iter()
+        .any(|ext| ext.eq_ignore_ascii_case(extension))
 }
 
 /// Checks if a file should be converted to .strm format
@@ -139,7 +338,9 @@ pub fn is_metadata_file(extension: &str) -> bool {
 ///
 /// # Returns
 ///
-/// `true` if the extension is in the STRM file list
+/// `true` if the extension is in the STRM file list, ignoring case
 pub fn is_streamable_file(extension: &str) -> bool {
-    FILE_STRM.contains(&extension)
+    FILE_STRM
+        .iter()
+        .any(|ext| ext.eq_ignore_ascii_case(extension))
 }
@@ -0,0 +1,211 @@
+//! Rules deciding what happens to each remote file.
+//!
+//! Profiles list rules under `classify`, checked in order before the
+//! `strm_extensions` and `metadata_extensions` lists; the first match wins:
+//!
+//! ```toml
+//! [[profiles.home.classify]]
+//! glob = "/music/**"
+//! mime = "audio/*"
+//! action = "download"
+//!
+//! [[profiles.home.classify]]
+//! extensions = ["iso"]
+//! action = "ignore"
+//! ```
+//!
+//! A rule matches when all of its given criteria match. Extensions and MIME
+//! types compare case-insensitively; MIME types are guessed from the file
+//! name. Globs follow [`crate::filter`]: without a `/` they match the file
+//! name, otherwise the full remote path.
+
+use anyhow::{Result, anyhow};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::api::types::{FILE_STRM, META_SUFF};
+
+/// What AutoSym does with a file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    /// Write a .strm file pointing at it
+    Strm,
+    /// Copy it next to the .strm files, like metadata
+    Copy,
+    /// Download it in full, with segmented downloads for large files
+    Download,
+    /// Leave it alone; Download skips it as well
+    Ignore,
+}
+
+/// One classification rule from the profile
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClassifyRule {
+    /// File extensions, without the dot
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Glob of file names or remote paths
+    pub glob: Option<String>,
+    /// MIME type such as `audio/flac`, or a whole type as `audio/*`
+    pub mime: Option<String>,
+    pub action: FileAction,
+}
+
+/// Compiled [`ClassifyRule`]
+#[derive(Debug)]
+struct Rule {
+    /// Lowercase extensions
+    extensions: Vec<String>,
+    glob: Option<(GlobMatcher, bool)>,
+    /// Lowercase MIME pattern
+    mime: Option<String>,
+    action: FileAction,
+}
+
+impl Rule {
+    fn new(rule: &ClassifyRule) -> Result<Self> {
+        if rule.extensions.is_empty() && rule.glob.is_none() && rule.mime.is_none() {
+            return Err(anyhow!(
+                "Classify rule for {:?} needs extensions, glob or mime",
+                rule.action
+            ));
+        }
+        let glob = rule
+            .glob
+            .as_deref()
+            .map(|pattern| {
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map(|glob| (glob.compile_matcher(), pattern.contains('/')))
+                    .map_err(|e| anyhow!("Invalid glob '{}': {}", pattern, e))
+            })
+            .transpose()?;
+        Ok(Self {
+            extensions: rule
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            glob,
+            mime: rule.mime.as_ref().map(|mime| mime.to_ascii_lowercase()),
+            action: rule.action,
+        })
+    }
+
+    /// Builds a rule matching a list of extensions
+    fn for_extensions(extensions: &[String], action: FileAction) -> Self {
+        Self {
+            extensions: extensions
+                .iter()
+                .map(|ext| ext.to_ascii_lowercase())
+                .collect(),
+            glob: None,
+            mime: None,
+            action,
+        }
+    }
+
+    fn is_match(&self, path: &str, name: &str, extension: Option<&str>) -> bool {
+        let extension_matches = self.extensions.is_empty() ||
+            extension
+                .is_some_and(|extension| self.extensions.iter().any(|ext| ext == extension));
+        let glob_matches = self
+            .glob
+            .as_ref()
+            .is_none_or(|(glob, full_path)| glob.is_match(if *full_path { path } else { name }));
+        let mime_matches = self.mime.as_deref().is_none_or(|pattern| {
+            mime_guess::from_path(name)
+                .iter()
+                .any(|mime| match pattern.strip_suffix("/*") {
+                    Some(type_) => mime.type_().as_str() == type_,
+                    None => mime.essence_str() == pattern,
+                })
+        });
+        extension_matches && glob_matches && mime_matches
+    }
+}
+
+/// Maps remote files to a [`FileAction`]
+#[derive(Debug)]
+pub struct Classifier {
+    rules: Vec<Rule>,
+}
+
+impl Default for Classifier {
+    /// Classifies by the built-in [`FILE_STRM`] and [`META_SUFF`] lists
+    fn default() -> Self {
+        let to_strings = |list: &[&str]| list.iter().map(|ext| ext.to_string()).collect::<Vec<_>>();
+        Self {
+            rules: vec![
+                Rule::for_extensions(&to_strings(&FILE_STRM), FileAction::Strm),
+                Rule::for_extensions(&to_strings(&META_SUFF), FileAction::Copy),
+            ],
+        }
+    }
+}
+
+impl Classifier {
+    /// Compiles the classification rules
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - Rules checked first, in order
+    /// * `strm_extensions` - Extensions turned into .strm files when no rule
+    ///   matches
+    /// * `copy_extensions` - Extensions copied when no rule matches
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a rule has no criteria or an invalid glob
+    pub fn new(
+        rules: &[ClassifyRule],
+        strm_extensions: &[String],
+        copy_extensions: &[String],
+    ) -> Result<Self> {
+        let mut compiled = rules.iter().map(Rule::new).collect::<Result<Vec<_>>>()?;
+        // An empty list would match every file
+        for (extensions, action) in [
+            (strm_extensions, FileAction::Strm),
+            (copy_extensions, FileAction::Copy),
+        ] {
+            if !extensions.is_empty() {
+                compiled.push(Rule::for_extensions(extensions, action));
+            }
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    /// Classifies a remote file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote path of the file
+    ///
+    /// # Returns
+    ///
+    /// The action of the first matching rule, or `None` if no rule matches
+    pub fn classify(&self, path: &str) -> Option<FileAction> {
+        let name = path.rsplit_once('/').map_or(path, |(_, name)| name);
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        self.rules
+            .iter()
+            .find(|rule| rule.is_match(path, name, extension.as_deref()))
+            .map(|rule| rule.action)
+    }
+
+    /// Returns every extension listed for the .strm action
+    ///
+    /// Used to map a local .strm file back to the remote files it may have
+    /// been made from.
+    pub fn strm_extensions(&self) -> impl Iterator<Item = &str> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.action == FileAction::Strm)
+            .flat_map(|rule| rule.extensions.iter().map(String::as_str))
+    }
+}
@@ -0,0 +1,351 @@
+//! Configuration file with named server profiles.
+//!
+//! The file is TOML, read from `--config` or
+//! `$XDG_CONFIG_HOME/alist_cli/config.toml`:
+//!
+//! ```toml
+//! default_profile = "home"
+//!
+//! [profiles.home]
+//! server_address = "http://192.168.0.201:5244"
+//! token = "alist-xxxx"
+//! threads = 8
+//! local_path = "/media/strm"
+//! ```
+//!
+//! Values are layered as command line flags, then `ALIST_*` environment
+//! variables, then the selected profile, then built-in defaults.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+
+use crate::{
+    Config,
+    api::types::{FILE_STRM, META_SUFF},
+    classify::{Classifier, ClassifyRule},
+    filter::{FilterRules, PathFilter},
+    media_server::MediaServer,
+};
+
+/// Server address used when neither flags nor the profile set one
+pub const DEFAULT_SERVER_ADDRESS: &str = "http://192.168.0.201:5244";
+
+/// Profile name used when none is selected and the file has no
+/// `default_profile`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Contents of the configuration file
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Profile used when `--profile` is not given
+    pub default_profile: Option<String>,
+    /// Named server profiles
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Settings for one server; every field is optional so profiles can be
+/// layered on top of each other
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub server_address: Option<String>,
+    pub token: Option<String>,
+    pub url_path: Option<String>,
+    pub threads: Option<usize>,
+    pub tpslimit: Option<u32>,
+    /// API request timeout in seconds
+    pub timeout: Option<u64>,
+    /// Connection timeout in seconds
+    pub connect_timeout: Option<u64>,
+    /// Download idle timeout in seconds
+    pub read_timeout: Option<u64>,
+    pub segments: Option<usize>,
+    /// Minimum file size in MiB for a segmented download
+    pub segment_threshold: Option<u64>,
+    /// Default local path for AutoSym and Download
+    pub local_path: Option<String>,
+    /// Default local path for Download, overriding `local_path`
+    pub download_path: Option<String>,
+    /// Extensions converted to .strm files
+    pub strm_extensions: Option<Vec<String>>,
+    /// Metadata extensions copied next to the .strm files
+    pub metadata_extensions: Option<Vec<String>>,
+    /// Classification rules checked before the extension lists, see
+    /// [`crate::classify`]
+    pub classify: Option<Vec<ClassifyRule>>,
+    /// Account used by `login` and to log in again when the token expires
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// File caching the session token (default: one per server under
+    /// `$XDG_CACHE_HOME/alist_cli/tokens`)
+    pub token_file: Option<PathBuf>,
+    /// File caching digests of local files (default:
+    /// `$XDG_CACHE_HOME/alist_cli/hashes.json`)
+    pub hash_cache: Option<PathBuf>,
+    /// Media servers told to rescan after AutoSym changed the library
+    pub media_servers: Option<Vec<MediaServer>>,
+    /// Globs of files to process, see [`crate::filter`]
+    pub include: Option<Vec<String>>,
+    /// Globs of files and directories to skip
+    pub exclude: Option<Vec<String>>,
+    /// Regular expressions of files to process
+    pub include_regex: Option<Vec<String>>,
+    /// Regular expressions of files and directories to skip
+    pub exclude_regex: Option<Vec<String>>,
+    /// Smallest file size to process, e.g. `100M`
+    pub min_size: Option<String>,
+    /// Largest file size to process, e.g. `4G`
+    pub max_size: Option<String>,
+    /// Only process files modified at or after this date
+    pub modified_after: Option<String>,
+    /// Only process files modified before this date
+    pub modified_before: Option<String>,
+}
+
+impl ConfigFile {
+    /// Returns the default configuration file location
+    ///
+    /// # Returns
+    ///
+    /// `$XDG_CONFIG_HOME/alist_cli/config.toml`, falling back to
+    /// `$HOME/.config`, or `None` if neither variable is set
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("alist_cli").join("config.toml"))
+    }
+
+    /// Parses a configuration file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the TOML file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config '{}': {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| anyhow!("Invalid config '{}': {}", path.display(), e))
+    }
+
+    /// Parses configuration file contents
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contents are not a valid configuration
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Loads the configuration from an explicit path or the default location.
+    ///
+    /// A missing file at the default location yields an empty configuration,
+    /// while an explicitly given file must exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path given with `--config`, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be loaded
+    pub fn discover(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::load(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    /// Selects a profile by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Profile given with `--profile`, or `None` for the file's
+    ///   `default_profile`
+    ///
+    /// # Returns
+    ///
+    /// The profile, or an empty one if no profile was requested and none is
+    /// configured
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a requested profile does not exist
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let requested = name.or(self.default_profile.as_deref());
+        let name = requested.unwrap_or(DEFAULT_PROFILE);
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None if requested.is_none() => Ok(Profile::default()),
+            None => Err(anyhow!("Profile '{}' not found in config", name)),
+        }
+    }
+}
+
+impl Profile {
+    /// Layers `other` on top of this profile
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Profile whose set fields take precedence
+    ///
+    /// # Returns
+    ///
+    /// The merged profile
+    pub fn merge(self, other: Profile) -> Profile {
+        Profile {
+            server_address: other.server_address.or(self.server_address),
+            token: other.token.or(self.token),
+            url_path: other.url_path.or(self.url_path),
+            threads: other.threads.or(self.threads),
+            tpslimit: other.tpslimit.or(self.tpslimit),
+            timeout: other.timeout.or(self.timeout),
+            connect_timeout: other.connect_timeout.or(self.connect_timeout),
+            read_timeout: other.read_timeout.or(self.read_timeout),
+            segments: other.segments.or(self.segments),
+            segment_threshold: other.segment_threshold.or(self.segment_threshold),
+            local_path: other.local_path.or(self.local_path),
+            download_path: other.download_path.or(self.download_path),
+            strm_extensions: other.strm_extensions.or(self.strm_extensions),
+            metadata_extensions: other.metadata_extensions.or(self.metadata_extensions),
+            classify: other.classify.or(self.classify),
+            username: other.username.or(self.username),
+            password: other.password.or(self.password),
+            token_file: other.token_file.or(self.token_file),
+            hash_cache: other.hash_cache.or(self.hash_cache),
+            media_servers: other.media_servers.or(self.media_servers),
+            include: other.include.or(self.include),
+            exclude: other.exclude.or(self.exclude),
+            include_regex: other.include_regex.or(self.include_regex),
+            exclude_regex: other.exclude_regex.or(self.exclude_regex),
+            min_size: other.min_size.or(self.min_size),
+            max_size: other.max_size.or(self.max_size),
+            modified_after: other.modified_after.or(self.modified_after),
+            modified_before: other.modified_before.or(self.modified_before),
+        }
+    }
+
+    /// Builds the runtime configuration, filling unset fields with defaults
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a filter or classification rule is invalid
+    pub fn to_config(&self) -> Result<Config> {
+        let threads = self.threads.unwrap_or(4);
+        let to_strings = |list: &[&str]| list.iter().map(|ext| ext.to_string()).collect();
+        let server_address = self
+            .server_address
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SERVER_ADDRESS.to_string());
+        let filter = PathFilter::new(FilterRules {
+            include: self.include.clone().unwrap_or_default(),
+            exclude: self.exclude.clone().unwrap_or_default(),
+            include_regex: self.include_regex.clone().unwrap_or_default(),
+            exclude_regex: self.exclude_regex.clone().unwrap_or_default(),
+            min_size: self.min_size.clone(),
+            max_size: self.max_size.clone(),
+            modified_after: self.modified_after.clone(),
+            modified_before: self.modified_before.clone(),
+        })?;
+        let classifier = Classifier::new(
+            self.classify.as_deref().unwrap_or_default(),
+            &self
+                .strm_extensions
+                .clone()
+                .unwrap_or_else(|| to_strings(&FILE_STRM)),
+            &self
+                .metadata_extensions
+                .clone()
+                .unwrap_or_else(|| to_strings(&META_SUFF)),
+        )?;
+        Ok(Config {
+            token_file: self
+                .token_file
+                .clone()
+                .or_else(|| default_token_path(&server_address)),
+            hash_cache: self
+                .hash_cache
+                .clone()
+                .or_else(|| cache_dir().map(|dir| dir.join("hashes.json"))),
+            server_address,
+            threads,
+            token: self.token.clone().unwrap_or_default(),
+            tpslimit: self.tpslimit.unwrap_or(u32::MAX),
+            concurrent_limit: std::cmp::max(threads, 10), // Min 10 for buffer_unordered operations
+            timeout: self.timeout.unwrap_or(10),
+            connect_timeout: self.connect_timeout.unwrap_or(10),
+            read_timeout: self.read_timeout.unwrap_or(30),
+            segments: self.segments.unwrap_or(1),
+            segment_threshold: self.segment_threshold.unwrap_or(64) * 1024 * 1024,
+            classifier,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            filter,
+        })
+    }
+}
+
+/// Returns the default token cache file for a server
+///
+/// # Arguments
+///
+/// * `server_address` - Address of the Alist server
+///
+/// # Returns
+///
+/// `$XDG_CACHE_HOME/alist_cli/tokens/<server>`, falling back to
+/// `$HOME/.cache`, or `None` if neither variable is set
+pub fn default_token_path(server_address: &str) -> Option<PathBuf> {
+    let cache_dir = cache_dir()?;
+    let server: String = server_address
+        .trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Some(cache_dir.join("tokens").join(server))
+}
+
+/// Returns the default journal of batch and regex renames
+///
+/// # Returns
+///
+/// `$XDG_CACHE_HOME/alist_cli/renames.json`, falling back to `$HOME/.cache`,
+/// or `None` if neither variable is set
+pub fn default_rename_journal() -> Option<PathBuf> {
+    Some(cache_dir()?.join("renames.json"))
+}
+
+/// Returns the cache directory of alist_cli
+///
+/// # Returns
+///
+/// `$XDG_CACHE_HOME/alist_cli`, falling back to `$HOME/.cache`, or `None` if
+/// neither variable is set
+fn cache_dir() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("alist_cli"))
+}
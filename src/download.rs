@@ -1,101 +1,235 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::Arc,
+};
 
 use anyhow::Result;
+use futures::{Stream, StreamExt, future};
 use indicatif::MultiProgress;
-use reqwest::Client;
-use tokio::{sync::Semaphore, task::JoinSet};
+use tokio::{
+    sync::Semaphore,
+    task::{JoinError, JoinSet},
+};
 
 use crate::{
-    api::{get_path_structure, get_raw_url},
-    get_config,
-    utils::{download_file_with_retries, provider_checksum},
+    api::{AlistClient, types::EntryWithPath},
+    classify::FileAction,
+    plan::Plan,
+    utils::{
+        file_ops::{is_local_copy_current, set_modified},
+        provider_checksum,
+    },
 };
 
-pub async fn download_folders(
-    url_path: String,
-    local_path: &str,
-    m_pb: MultiProgress,
-) -> Result<()> {
-    let client = Arc::new(Client::builder().no_proxy().build()?);
-    let res = get_path_structure(url_path, m_pb.clone(), Arc::clone(&client)).await?;
-    let mut tasks = JoinSet::new();
-    let semaphore = Arc::new(Semaphore::new(get_config().threads));
-
-    for f in res {
-        let client_cloned = Arc::clone(&client);
-        let mut local_path_buf = PathBuf::from(local_path);
-        let semaphore_cloned = Arc::clone(&semaphore);
-
-        // Remove leading "/" from f.path_str
-        let relative_p2 = f.path_str.trim_start_matches('/');
-        local_path_buf.push(relative_p2);
-
-        let m_clone = m_pb.clone();
-        let file_path = f.path_str.clone();
-        tasks.spawn(async move {
-            // use semaphore to limit the concurrent downloader
-            let _permit = semaphore_cloned.acquire().await?;
-            let raw_url = get_raw_url(&client_cloned, &f).await?;
-            let hash_info = if provider_checksum(&f) {
-                f.entry.hash_info.clone()
-            } else {
-                None
-            };
-
-            download_file_with_retries(
-                &raw_url,
-                &local_path_buf,
-                &client_cloned,
-                hash_info,
-                m_clone,
-            )
-            .await
-            .map(|_| file_path)
-        });
-    }
-
-    let mut failed_files = Vec::new();
-    let mut succeeded = 0;
-    let mut failed = 0;
+/// Outcome of the downloads started by [`AlistClient::download_files`]
+#[derive(Default)]
+struct DownloadSummary {
+    downloaded: Vec<PathBuf>,
+    failed_files: Vec<String>,
+}
 
-    while let Some(result) = tasks.join_next().await {
+impl DownloadSummary {
+    /// Records the result of one download task
+    fn record(&mut self, result: Result<Result<PathBuf>, JoinError>) {
         match result {
             Ok(Ok(file_path)) => {
-                succeeded += 1;
-                tracing::debug!("Successfully downloaded: {}", file_path);
+                tracing::debug!("Successfully downloaded: {}", file_path.display());
+                self.downloaded.push(file_path);
             }
             Ok(Err(e)) => {
-                failed += 1;
                 let error_msg = format!("Download error: {}", e);
                 tracing::error!("{}", error_msg);
-                failed_files.push(error_msg);
+                self.failed_files.push(error_msg);
             }
             Err(e) => {
-                failed += 1;
                 let error_msg = format!("Task join error: {}", e);
                 tracing::error!("{}", error_msg);
-                failed_files.push(error_msg);
+                self.failed_files.push(error_msg);
             }
         }
     }
+}
 
-    // Report summary
-    tracing::info!(
-        "Download complete: {} succeeded, {} failed",
-        succeeded,
-        failed
-    );
-
-    if !failed_files.is_empty() {
-        tracing::warn!("Failed downloads:");
-        for error in &failed_files {
-            tracing::warn!("  - {}", error);
-        }
-        return Err(anyhow::anyhow!(
-            "Download completed with {} errors. See logs for details.",
+impl AlistClient {
+    /// Downloads every file under a remote path, except those classified as
+    /// [`FileAction::Ignore`]
+    ///
+    /// # Arguments
+    ///
+    /// * `url_path` - Remote directory to download
+    /// * `local_path` - Local directory mirroring the remote root
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any download failed
+    pub async fn download_folders(
+        &self,
+        url_path: String,
+        local_path: &str,
+        m_pb: MultiProgress,
+    ) -> Result<()> {
+        let files = self.downloadable_files(url_path, m_pb.clone());
+        let DownloadSummary {
+            downloaded,
+            failed_files,
+        } = self.download_entries(files, local_path, m_pb).await?;
+        let failed = failed_files.len();
+
+        // Report summary
+        tracing::info!(
+            "Download complete: {} succeeded, {} failed",
+            downloaded.len(),
             failed
-        ));
+        );
+
+        if !failed_files.is_empty() {
+            tracing::warn!("Failed downloads:");
+            for error in &failed_files {
+                tracing::warn!("  - {}", error);
+            }
+            return Err(anyhow::anyhow!(
+                "Download completed with {} errors. See logs for details.",
+                failed
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Lists what [`AlistClient::download_folders`] would download, without
+    /// writing anything, see [`Plan::push_download`]
+    ///
+    /// # Arguments
+    ///
+    /// * `url_path` - Remote directory to download
+    /// * `local_path` - Local directory mirroring the remote root
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    pub async fn plan_download(
+        &self,
+        url_path: String,
+        local_path: &str,
+        m_pb: MultiProgress,
+    ) -> Plan {
+        let mut plan = Plan::default();
+        let mut files = pin!(self.downloadable_files(url_path, m_pb));
+        while let Some(f) = files.next().await {
+            plan.push_download(Path::new(local_path), &f).await;
+        }
+        plan
     }
 
-    Ok(())
+    /// Streams the files under a remote path that Download fetches
+    fn downloadable_files(
+        &self,
+        url_path: String,
+        m_pb: MultiProgress,
+    ) -> impl Stream<Item = EntryWithPath> {
+        self.stream_path_structure(url_path, m_pb).filter_map(|f| {
+            future::ready(match f {
+                Ok(f) => (!f.entry.is_dir &&
+                    self.config().classify(&f.path_str) != Some(FileAction::Ignore))
+                .then_some(f),
+                Err(e) => {
+                    tracing::warn!("Skipping unlisted directory: {}", e);
+                    None
+                }
+            })
+        })
+    }
+
+    /// Downloads files as they arrive, for the files AutoSym classifies as
+    /// [`FileAction::Download`]
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Stream of remote files
+    /// * `local_path` - Local directory mirroring the remote root
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The local files that were downloaded
+    ///
+    /// # Errors
+    ///
+    /// Individual file failures are logged but don't stop the overall operation
+    pub async fn download_files(
+        &self,
+        files: impl Stream<Item = EntryWithPath>,
+        local_path: &str,
+        m_pb: MultiProgress,
+    ) -> Result<Vec<PathBuf>> {
+        let summary = self.download_entries(files, local_path, m_pb).await?;
+        if !summary.failed_files.is_empty() {
+            tracing::warn!("{} downloads failed", summary.failed_files.len());
+        }
+        Ok(summary.downloaded)
+    }
+
+    /// Runs up to `threads` downloads at once and collects their results
+    async fn download_entries(
+        &self,
+        files: impl Stream<Item = EntryWithPath>,
+        local_path: &str,
+        m_pb: MultiProgress,
+    ) -> Result<DownloadSummary> {
+        let mut files = pin!(files);
+        let mut tasks = JoinSet::new();
+        let semaphore = Arc::new(Semaphore::new(self.config().threads));
+        let mut summary = DownloadSummary::default();
+
+        while let Some(f) = files.next().await {
+            // Wait for a free download slot before pulling more entries
+            let permit = Arc::clone(&semaphore).acquire_owned().await?;
+            let client = self.clone();
+            let mut local_path_buf = PathBuf::from(local_path);
+
+            // Remove leading "/" from f.path_str
+            let relative_p2 = f.path_str.trim_start_matches('/');
+            local_path_buf.push(relative_p2);
+
+            let m_clone = m_pb.clone();
+            tasks.spawn(async move {
+                let _permit = permit;
+                // Nothing to fetch if the local copy has the remote size and time
+                if is_local_copy_current(&local_path_buf, &f.entry).await {
+                    tracing::trace!("Download is current: {}", local_path_buf.display());
+                    return Ok(local_path_buf);
+                }
+                let raw_url = client.get_raw_url(&f).await?;
+                let hash_info = if provider_checksum(&f) {
+                    f.entry.hash_info.clone()
+                } else {
+                    None
+                };
+
+                client
+                    .download_file_with_retries(
+                        &raw_url,
+                        &local_path_buf,
+                        hash_info,
+                        f.entry.size,
+                        m_clone,
+                    )
+                    .await?;
+                // Lets the next run recognize the download as current
+                if let Err(e) = set_modified(&local_path_buf, &f.entry.modified).await {
+                    tracing::debug!("Kept mtime of {}: {}", local_path_buf.display(), e);
+                }
+                Ok(local_path_buf)
+            });
+
+            // Collect finished downloads so results don't pile up
+            while let Some(result) = tasks.try_join_next() {
+                summary.record(result);
+            }
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            summary.record(result);
+        }
+        Ok(summary)
+    }
 }
@@ -0,0 +1,253 @@
+//! Include/exclude rules restricting which remote entries are processed.
+//!
+//! Glob patterns without a `/` match the entry name, patterns with a `/`
+//! match the full remote path, e.g. `*.mkv` or `/movies/**/extras`. Regular
+//! expressions are searched in the full remote path.
+//!
+//! Exclude rules apply to files and directories; an excluded directory is not
+//! listed at all. Include rules and the size and date predicates only apply to
+//! files.
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::api::types::EntryWithPath;
+
+/// Filter rules as given on the command line or in the profile
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct FilterRules {
+    /// Globs a file must match one of, if any are given
+    pub include: Vec<String>,
+    /// Globs of files and directories to skip
+    pub exclude: Vec<String>,
+    /// Regular expressions a file must match one of, if any are given
+    pub include_regex: Vec<String>,
+    /// Regular expressions of files and directories to skip
+    pub exclude_regex: Vec<String>,
+    /// Smallest file size to keep, e.g. `100M`
+    pub min_size: Option<String>,
+    /// Largest file size to keep, e.g. `4G`
+    pub max_size: Option<String>,
+    /// Keep files modified at or after this date or RFC 3339 time
+    pub modified_after: Option<String>,
+    /// Keep files modified before this date or RFC 3339 time
+    pub modified_before: Option<String>,
+}
+
+/// Compiled glob and regex patterns of one kind
+#[derive(Debug, Default)]
+struct Patterns {
+    /// Globs matched against the entry name
+    names: GlobSet,
+    /// Globs matched against the full remote path
+    paths: GlobSet,
+    regexes: RegexSet,
+}
+
+impl Patterns {
+    fn new(globs: &[String], regexes: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in globs {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| anyhow!("Invalid glob '{}': {}", pattern, e))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        Ok(Self {
+            names: names.build()?,
+            paths: paths.build()?,
+            regexes: RegexSet::new(regexes).map_err(|e| anyhow!("Invalid regex: {}", e))?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty() && self.regexes.is_empty()
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        let name = path.rsplit_once('/').map_or(path, |(_, name)| name);
+        self.names.is_match(name) || self.paths.is_match(path) || self.regexes.is_match(path)
+    }
+}
+
+/// Compiled [`FilterRules`]
+#[derive(Debug, Default)]
+pub struct PathFilter {
+    rules: FilterRules,
+    include: Patterns,
+    exclude: Patterns,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime<FixedOffset>>,
+    modified_before: Option<DateTime<FixedOffset>>,
+}
+
+/// Parses a size in bytes with an optional binary unit suffix
+///
+/// # Arguments
+///
+/// * `size` - A number, optionally followed by `K`, `M`, `G` or `T` (e.g.
+///   `512K`, `1.5G`)
+///
+/// # Errors
+///
+/// Returns an error if the number or the unit is invalid
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{}'", size))?;
+    let multiplier: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(anyhow!("Invalid size unit in '{}'", size)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses an RFC 3339 time, or a `YYYY-MM-DD` date taken as midnight UTC
+///
+/// # Errors
+///
+/// Returns an error if the value is neither
+pub fn parse_date(date: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|day| day.and_time(Default::default()).and_utc().fixed_offset())
+        })
+        .map_err(|_| anyhow!("Invalid date '{}', expected YYYY-MM-DD or RFC 3339", date))
+}
+
+impl PathFilter {
+    /// Compiles filter rules
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern, size or date is invalid
+    pub fn new(rules: FilterRules) -> Result<Self> {
+        Ok(Self {
+            include: Patterns::new(&rules.include, &rules.include_regex)?,
+            exclude: Patterns::new(&rules.exclude, &rules.exclude_regex)?,
+            min_size: rules.min_size.as_deref().map(parse_size).transpose()?,
+            max_size: rules.max_size.as_deref().map(parse_size).transpose()?,
+            modified_after: rules
+                .modified_after
+                .as_deref()
+                .map(parse_date)
+                .transpose()?,
+            modified_before: rules
+                .modified_before
+                .as_deref()
+                .map(parse_date)
+                .transpose()?,
+            rules,
+        })
+    }
+
+    /// Returns the rules this filter was compiled from
+    pub fn rules(&self) -> &FilterRules {
+        &self.rules
+    }
+
+    /// Checks if a listed entry should be processed; for a directory this
+    /// decides whether it is listed
+    ///
+    /// A file whose `modified` cannot be parsed passes the date predicates.
+    pub fn allows(&self, entry: &EntryWithPath) -> bool {
+        if self.exclude.is_match(&entry.path_str) {
+            return false;
+        }
+        if entry.entry.is_dir {
+            return true;
+        }
+        let modified = DateTime::parse_from_rfc3339(&entry.entry.modified).ok();
+        self.allows_file(&entry.path_str, entry.entry.size, modified)
+    }
+
+    /// Checks if a file should be processed by the include rules and the
+    /// size and date predicates; exclude rules are only checked on the file
+    /// itself
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote file path
+    /// * `size` - File size in bytes
+    /// * `modified` - Modification time, `None` passes the date predicates
+    pub fn allows_file(
+        &self,
+        path: &str,
+        size: u64,
+        modified: Option<DateTime<FixedOffset>>,
+    ) -> bool {
+        !self.exclude.is_match(path) &&
+            (self.include.is_empty() || self.include.is_match(path)) &&
+            self.min_size.is_none_or(|min| size >= min) &&
+            self.max_size.is_none_or(|max| size <= max) &&
+            self.modified_after
+                .is_none_or(|after| modified.is_none_or(|modified| modified >= after)) &&
+            self.modified_before
+                .is_none_or(|before| modified.is_none_or(|modified| modified < before))
+    }
+
+    /// Checks if a local copy of a remote file is in scope, taking the size
+    /// and modification time from the local file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote file path the local file mirrors
+    /// * `metadata` - Metadata of the local file
+    pub fn allows_local_file(&self, path: &str, metadata: &std::fs::Metadata) -> bool {
+        let modified = metadata
+            .modified()
+            .ok()
+            .map(|modified| DateTime::<Utc>::from(modified).fixed_offset());
+        self.allows_path(path) && self.allows_file(path, metadata.len(), modified)
+    }
+
+    /// Checks if files are filtered by their size
+    pub fn has_size_limits(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    /// Checks if a remote file path is in scope by the include and exclude
+    /// rules alone, for files whose size and date are unknown
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Remote file path
+    ///
+    /// # Returns
+    ///
+    /// `false` if the file or one of its parent directories is excluded, or
+    /// the file does not match the include rules
+    pub fn allows_path(&self, path: &str) -> bool {
+        let excluded = path
+            .match_indices('/')
+            .skip(1)
+            .map(|(end, _)| &path[..end])
+            .chain([path])
+            .any(|prefix| self.exclude.is_match(prefix));
+        !excluded && (self.include.is_empty() || self.include.is_match(path))
+    }
+}
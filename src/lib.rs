@@ -5,17 +5,26 @@
 //! management.
 
 pub mod api;
+pub mod classify;
+pub mod config;
 pub mod download;
+pub mod filter;
+pub mod media_server;
+pub mod mirror;
+pub mod plan;
+pub mod snapshot;
 pub mod tracing_bridge;
+pub mod upload;
 pub mod utils;
 
+pub use api::AlistClient;
+
 pub use std::{
     collections::HashSet,
     path::{Path, PathBuf},
-    sync::{Arc, OnceLock},
+    sync::Arc,
 };
 
-#[derive(Debug)]
 pub struct Config {
     pub server_address: String,
     pub threads: usize,
@@ -23,6 +32,56 @@ pub struct Config {
     pub tpslimit: u32,
     pub concurrent_limit: usize,
     pub timeout: u64,
+    /// Timeout in seconds for establishing a connection
+    pub connect_timeout: u64,
+    /// Download idle timeout in seconds, the longest wait for the response
+    /// headers or the next chunk
+    pub read_timeout: u64,
+    /// Number of concurrent range requests per large download, `1` disables
+    /// segmented downloads
+    pub segments: usize,
+    /// Minimum file size in bytes for a segmented download
+    pub segment_threshold: u64,
+    /// Decides which files become .strm files, are copied or downloaded
+    pub classifier: classify::Classifier,
+    /// Account used to log in again when the token expires
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// File caching the session token between runs
+    pub token_file: Option<PathBuf>,
+    /// File caching digests of local files between runs, `None` to keep
+    /// them in memory only
+    pub hash_cache: Option<PathBuf>,
+    /// Which remote entries traversals yield
+    pub filter: filter::PathFilter,
+}
+
+/// Placeholder printed instead of a secret
+const REDACTED: &str = "<redacted>";
+
+impl std::fmt::Debug for Config {
+    /// Formats the config with the token and password redacted, so it can be
+    /// logged
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("server_address", &self.server_address)
+            .field("threads", &self.threads)
+            .field("token", &(!self.token.is_empty()).then_some(REDACTED))
+            .field("tpslimit", &self.tpslimit)
+            .field("concurrent_limit", &self.concurrent_limit)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("segments", &self.segments)
+            .field("segment_threshold", &self.segment_threshold)
+            .field("classifier", &self.classifier)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("token_file", &self.token_file)
+            .field("hash_cache", &self.hash_cache)
+            .field("filter", &self.filter)
+            .finish()
+    }
 }
 
 impl Config {
@@ -35,14 +94,21 @@ impl Config {
             tpslimit: u32::MAX,
             concurrent_limit: 4,
             timeout: 10,
+            connect_timeout: 10,
+            read_timeout: 30,
+            segments: 1,
+            segment_threshold: 64 * 1024 * 1024,
+            classifier: classify::Classifier::default(),
+            username: None,
+            password: None,
+            token_file: None,
+            hash_cache: None,
+            filter: filter::PathFilter::default(),
         }
     }
-}
 
-pub static CONFIG: OnceLock<Config> = OnceLock::new();
-
-/// Helper to get CONFIG reference, initializing with test defaults if not
-/// already set
-pub fn get_config() -> &'static Config {
-    CONFIG.get_or_init(Config::default_test_config)
+    /// Classifies a remote file, see [`classify::Classifier::classify`]
+    pub fn classify(&self, path: &str) -> Option<classify::FileAction> {
+        self.classifier.classify(path)
+    }
 }
@@ -1,48 +1,156 @@
 use alist_cli::*;
+use std::{pin::pin, time::Duration};
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Result, anyhow};
+use api::{
+    client::STREAM_BUFFER,
+    manage::{check_renames, parse_rename_list, regex_renames, write_rename_preview},
+    tasks::{TaskKind, TaskState, write_task_table},
+    types::TaskInfo,
+};
+use clap::{Args, Parser};
+use classify::FileAction;
+use config::{ConfigFile, Profile};
+use futures::{SinkExt, StreamExt, channel::mpsc};
 use indicatif::MultiProgress;
+use media_server::MediaServer;
+use mirror::{Compare, MirrorOptions};
+use plan::{Plan, PlanAction, PlanFormat};
+use regex::Regex;
+use snapshot::Snapshot;
 use tokio::fs;
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
 use tracing_bridge::MakeSuspendingWriter;
-use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt};
+use tracing_subscriber::{
+    EnvFilter, Registry,
+    fmt::{self, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+};
+use upload::{OverwritePolicy, UploadMethod, UploadOptions, UploadSummary};
+use utils::{
+    file_ops::is_local_copy_current,
+    lock::RunLock,
+    rename_journal::{RenameBatch, RenameJournal},
+    trash::{DEFAULT_TRASH_DIR, DeleteLimit, Trash},
+};
 use walkdir::WalkDir;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// alist server addr
+    /// config file (default: $XDG_CONFIG_HOME/alist_cli/config.toml)
+    #[arg(short, long, global = true, env = "ALIST_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// profile from the config file to use
+    #[arg(short, long, global = true, env = "ALIST_PROFILE")]
+    profile: Option<String>,
+
+    /// alist server addr [default: http://192.168.0.201:5244]
+    #[arg(short, long, global = true, env = "ALIST_SERVER_ADDRESS")]
+    server_address: Option<String>,
+
+    /// remote path to work on [default: /]
+    #[arg(short, long, global = true, env = "ALIST_URL_PATH")]
+    url_path: Option<String>,
+
+    /// number of concurrent downloads [default: 4]
+    #[arg(short = 'j', long, global = true, env = "ALIST_THREADS")]
+    threads: Option<usize>,
+
+    /// alist token
     #[arg(
-        short,
+        short = 't',
         long,
         global = true,
-        default_value = "http://192.168.0.201:5244"
+        env = "ALIST_TOKEN",
+        hide_env_values = true
     )]
-    server_address: String,
+    token: Option<String>,
 
-    #[arg(short, long, global = true, default_value = "/")]
-    url_path: String,
+    /// account used to log in and to refresh an expired token
+    #[arg(long, global = true, env = "ALIST_USERNAME")]
+    username: Option<String>,
 
-    #[arg(short = 'j', long, global = true, default_value_t = 4)]
-    threads: usize,
+    /// password of the account
+    #[arg(long, global = true, env = "ALIST_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
 
-    /// alist token
-    #[arg(short = 't', long, global = true, default_value = "")]
-    token: String,
+    /// file caching the token (default:
+    /// $XDG_CACHE_HOME/alist_cli/tokens/<server>)
+    #[arg(long, global = true, env = "ALIST_TOKEN_FILE")]
+    token_file: Option<PathBuf>,
 
-    /// Limit HTTP transactions per second to this
+    /// file caching digests of local files (default:
+    /// $XDG_CACHE_HOME/alist_cli/hashes.json)
+    #[arg(long, global = true, env = "ALIST_HASH_CACHE")]
+    hash_cache: Option<PathBuf>,
+
+    /// Limit HTTP transactions per second to this [default: unlimited]
     #[arg(
         long,
         global = true,
-        default_value_t = u32::MAX,
+        env = "ALIST_TPSLIMIT",
         allow_negative_numbers(false)
     )]
-    tpslimit: u32,
+    tpslimit: Option<u32>,
+
+    /// API request timeout in seconds [default: 10]
+    #[arg(long, global = true, env = "ALIST_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// Timeout in seconds for establishing a connection [default: 10]
+    #[arg(long, global = true, env = "ALIST_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+
+    /// Download idle timeout in seconds, for the response headers and
+    /// between received chunks; downloads have no total time limit
+    /// [default: 30]
+    #[arg(long, global = true, env = "ALIST_READ_TIMEOUT")]
+    read_timeout: Option<u64>,
+
+    /// Split large downloads into this many concurrent range requests
+    /// [default: 1]
+    #[arg(long, global = true, env = "ALIST_SEGMENTS")]
+    segments: Option<usize>,
+
+    /// Minimum file size in MiB for a segmented download [default: 64]
+    #[arg(long, global = true, env = "ALIST_SEGMENT_THRESHOLD")]
+    segment_threshold: Option<u64>,
+
+    /// Only process files matching this glob; globs without '/' match the
+    /// file name, others the full remote path (repeatable)
+    #[arg(long, global = true, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files and directories matching this glob (repeatable)
+    #[arg(long, global = true, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only process files whose remote path matches this regex (repeatable)
+    #[arg(long, global = true, value_name = "REGEX")]
+    include_regex: Vec<String>,
+
+    /// Skip files and directories whose remote path matches this regex
+    /// (repeatable)
+    #[arg(long, global = true, value_name = "REGEX")]
+    exclude_regex: Vec<String>,
+
+    /// Skip files smaller than this, e.g. 100M
+    #[arg(long, global = true, value_name = "SIZE")]
+    min_size: Option<String>,
+
+    /// Skip files larger than this, e.g. 4G
+    #[arg(long, global = true, value_name = "SIZE")]
+    max_size: Option<String>,
 
-    /// Request timeout in seconds
-    #[arg(long, global = true, default_value_t = 10)]
-    timeout: u64,
+    /// Skip files modified before this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, global = true, value_name = "DATE")]
+    modified_after: Option<String>,
+
+    /// Skip files modified at or after this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, global = true, value_name = "DATE")]
+    modified_before: Option<String>,
 
     #[command(subcommand)]
     command: Commands,
@@ -51,65 +159,871 @@ struct Cli {
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 enum Commands {
-    #[command(arg_required_else_help = true)]
     /// Create and refresh strm file and metadata for the Alist server
     AutoSym {
+        #[command(flatten)]
+        sync: AutoSymArgs,
+
+        #[command(flatten)]
+        plan: PlanArgs,
+    },
+    /// Keep the strm library in sync by running AutoSym on a schedule
+    Watch {
+        #[command(flatten)]
+        sync: AutoSymArgs,
+
+        /// Seconds between runs
+        #[arg(long, default_value_t = 3600)]
+        interval: u64,
+
+        /// Cron expression with seconds, e.g. "0 */30 * * * *", used instead
+        /// of --interval
+        #[arg(long, conflicts_with = "interval")]
+        cron: Option<String>,
+    },
+    /// Download every file under --url-path into the local path
+    Download {
         /// download path directory
-        #[arg(short, long, required = true)]
-        local_path: String,
+        #[arg(short, long, env = "ALIST_DOWNLOAD_PATH")]
+        local_path: Option<String>,
+
+        #[command(flatten)]
+        plan: PlanArgs,
+    },
+    /// Upload a local file, or the contents of a local directory, into
+    /// --url-path
+    Upload {
+        /// file or directory to upload
+        local_path: PathBuf,
+
+        /// what to do with remote files that already exist
+        #[arg(long, value_enum, default_value_t = OverwritePolicy::Overwrite)]
+        overwrite: OverwritePolicy,
 
-        /// Do the actual remove the non-existent file
+        #[command(flatten)]
+        upload: UploadArgs,
+    },
+    /// Upload the new and changed files of a local directory into
+    /// --url-path, the reverse of Download
+    MirrorUp {
+        /// local directory to mirror
+        local_path: PathBuf,
+
+        /// how to tell whether a remote file matches the local one
+        #[arg(long, value_enum, default_value_t = Compare::Size)]
+        compare: Compare,
+
+        /// Delete remote files that no longer exist locally
         #[arg(short, long, default_value_t = false)]
         delete: bool,
+
+        /// Abort deleting if more remote files would be removed, either a
+        /// count (500) or a percentage of the remote files (10%)
+        #[arg(long)]
+        max_delete: Option<DeleteLimit>,
+
+        #[command(flatten)]
+        upload: UploadArgs,
     },
-    Download {
-        /// download path directory
-        #[arg(short, long, required = true)]
-        local_path: String,
+    /// Create a remote directory along with any missing parents
+    Mkdir {
+        /// remote directory, relative paths start at --url-path
+        path: String,
     },
+    /// Rename a remote file or directory in place, or many entries of a
+    /// directory with --regex or --batch
+    Rename {
+        /// remote file or directory, or with --regex and --batch the
+        /// directory whose entries are renamed; relative paths start at
+        /// --url-path
+        #[arg(required_unless_present = "undo")]
+        path: Option<String>,
+
+        /// new name, without a directory
+        #[arg(
+            required_unless_present_any = ["regex", "batch", "undo"],
+            conflicts_with_all = ["regex", "batch", "undo"]
+        )]
+        name: Option<String>,
+
+        /// replace an existing entry with the new name
+        #[arg(long, default_value_t = false)]
+        overwrite: bool,
+
+        #[command(flatten)]
+        many: RenameManyArgs,
+    },
+    /// Move remote files and directories into a remote directory
+    #[command(alias = "move")]
+    Mv {
+        #[command(flatten)]
+        transfer: TransferArgs,
+    },
+    /// Copy remote files and directories into a remote directory
+    #[command(alias = "copy")]
+    Cp {
+        #[command(flatten)]
+        transfer: TransferArgs,
+    },
+    /// Remove remote files and directories
+    #[command(alias = "remove")]
+    Rm {
+        /// remote paths whose last component may be a glob, e.g.
+        /// '/incoming/*.nfo'
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// also remove directories and everything in them
+        #[arg(short, long, default_value_t = false)]
+        recursive: bool,
+    },
+    /// List, watch, cancel or retry background tasks on the server
+    Tasks {
+        #[command(subcommand)]
+        action: TaskAction,
+    },
+    /// Log in with --username/--password and cache the token
+    Login {
+        /// one-time code for accounts with two-factor authentication
+        #[arg(long)]
+        otp: Option<String>,
+
+        /// send the plain password instead of its salted hash
+        #[arg(long, default_value_t = false)]
+        plain: bool,
+    },
+    /// List, restore or purge files moved to the trash by AutoSym
+    Trash {
+        /// download path directory the trash belongs to
+        #[arg(short, long, env = "ALIST_LOCAL_PATH")]
+        local_path: Option<String>,
+
+        /// trash directory (default: <local_path>/.alist_trash)
+        #[arg(long)]
+        trash_dir: Option<String>,
+
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+}
+
+/// Options shared by AutoSym and Watch
+#[derive(Args, Clone)]
+struct AutoSymArgs {
+    /// download path directory
+    #[arg(short, long, env = "ALIST_LOCAL_PATH")]
+    local_path: Option<String>,
+
+    /// Do the actual remove the non-existent file
+    #[arg(short, long, default_value_t = false)]
+    delete: bool,
+
+    /// Abort pruning if more files would be removed, either a count (500)
+    /// or a percentage of the local files (10%)
+    #[arg(long)]
+    max_delete: Option<DeleteLimit>,
+
+    /// Move pruned files into a dated trash batch instead of deleting
+    /// them, optionally in DIR (default: <local_path>/.alist_trash)
+    #[arg(long, value_name = "DIR", num_args = 0..=1)]
+    trash: Option<Option<String>>,
+
+    /// How to build the URL inside each strm file
+    #[arg(long, value_enum, default_value_t = api::StrmUrlMode::Raw)]
+    strm_url: api::StrmUrlMode,
+
+    /// List every directory again instead of reusing unchanged listings
+    /// from the last run's snapshot
+    #[arg(long, default_value_t = false)]
+    full_scan: bool,
 }
 
+/// Options shared by Mv and Cp
+#[derive(Args)]
+struct TransferArgs {
+    /// remote paths whose last component may be a glob, e.g.
+    /// '/incoming/*.mkv'
+    #[arg(required = true)]
+    sources: Vec<String>,
+
+    /// remote directory receiving them
+    destination: String,
+
+    /// replace existing entries in the destination
+    #[arg(long, default_value_t = false)]
+    overwrite: bool,
+
+    /// wait until the tasks the server started for transfers between
+    /// storages have finished
+    #[arg(long, default_value_t = false)]
+    wait: bool,
+}
+
+/// Options of Rename for renaming many entries at once
+#[derive(Args)]
+struct RenameManyArgs {
+    /// rename every entry whose name matches this regex
+    #[arg(long, requires = "replace", conflicts_with_all = ["batch", "undo"])]
+    regex: Option<String>,
+
+    /// replacement for --regex, $1 or ${name} refer to capture groups
+    #[arg(long, requires = "regex")]
+    replace: Option<String>,
+
+    /// file of 'old name<TAB>new name' lines, '-' for standard input
+    #[arg(long, conflicts_with = "undo")]
+    batch: Option<PathBuf>,
+
+    /// revert the most recent --regex or --batch rename on this server
+    #[arg(long, default_value_t = false, conflicts_with = "path")]
+    undo: bool,
+
+    /// only print the renames
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// rename without asking for confirmation
+    #[arg(short, long, default_value_t = false)]
+    yes: bool,
+
+    /// journal of renames for --undo (default:
+    /// $XDG_CACHE_HOME/alist_cli/renames.json)
+    #[arg(long, env = "ALIST_RENAME_JOURNAL")]
+    journal: Option<PathBuf>,
+}
+
+impl RenameManyArgs {
+    fn is_set(&self) -> bool {
+        self.regex.is_some() || self.batch.is_some() || self.undo
+    }
+}
+
+/// Options shared by Upload and MirrorUp
+#[derive(Args)]
+struct UploadArgs {
+    /// upload endpoint: the raw request body or a multipart form
+    #[arg(long, value_enum, default_value_t = UploadMethod::Stream)]
+    method: UploadMethod,
+
+    /// let the server move files to the storage in background tasks
+    #[arg(long, default_value_t = false)]
+    as_task: bool,
+
+    /// send MD5 and SHA1 hashes so storages supporting rapid upload can skip
+    /// the transfer
+    #[arg(long, default_value_t = false)]
+    rapid: bool,
+
+    /// wait until the server finished the upload tasks
+    #[arg(long, default_value_t = false, requires = "as_task")]
+    wait: bool,
+}
+
+impl UploadArgs {
+    /// Builds the upload options with an overwrite policy
+    fn options(&self, overwrite: OverwritePolicy) -> UploadOptions {
+        UploadOptions {
+            method: self.method,
+            as_task: self.as_task,
+            overwrite,
+            rapid: self.rapid,
+        }
+    }
+}
+
+// Options of AutoSym and Download for reviewing a run before it happens. Not
+// a doc comment, clap would show it as the help of a subcommand without one.
+#[derive(Args)]
+struct PlanArgs {
+    /// Print what would be written, downloaded or pruned without touching
+    /// the local path
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// How to print the --dry-run plan
+    #[arg(long, value_enum, default_value_t = PlanFormat::Table)]
+    plan_format: PlanFormat,
+}
+
+#[derive(Parser)]
+enum TrashAction {
+    /// List the trash batches
+    List,
+    /// Move the files of a batch back into the local path
+    Restore {
+        /// batch to restore (default: the most recent one)
+        batch: Option<String>,
+    },
+    /// Permanently delete trash batches
+    Purge {
+        /// Only purge batches older than this many days
+        #[arg(long)]
+        older_than_days: Option<i64>,
+    },
+}
+
+#[derive(Parser)]
+enum TaskAction {
+    /// List the pending and running tasks
+    List {
+        /// list finished tasks instead
+        #[arg(long, default_value_t = false)]
+        done: bool,
+
+        #[command(flatten)]
+        queues: TaskQueueArgs,
+    },
+    /// Show live progress until no tasks are pending or running
+    Watch {
+        /// Seconds between polls
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+
+        #[command(flatten)]
+        queues: TaskQueueArgs,
+    },
+    /// Cancel pending or running tasks
+    Cancel {
+        #[command(flatten)]
+        tasks: TaskIdArgs,
+    },
+    /// Run failed or canceled tasks again
+    Retry {
+        #[command(flatten)]
+        tasks: TaskIdArgs,
+    },
+    /// Remove finished tasks
+    ClearDone {
+        #[command(flatten)]
+        queues: TaskQueueArgs,
+    },
+}
+
+/// Task queues a Tasks action applies to
+#[derive(Args)]
+struct TaskQueueArgs {
+    /// task queue, repeatable (default: all)
+    #[arg(short, long = "kind", value_enum)]
+    kinds: Vec<TaskKind>,
+}
+
+impl TaskQueueArgs {
+    fn kinds(&self) -> Vec<TaskKind> {
+        if self.kinds.is_empty() {
+            TaskKind::ALL.to_vec()
+        } else {
+            self.kinds.clone()
+        }
+    }
+}
+
+/// Tasks picked by id
+#[derive(Args)]
+struct TaskIdArgs {
+    /// task queue holding the tasks
+    #[arg(short, long, value_enum)]
+    kind: TaskKind,
+
+    /// task ids, as shown by `tasks list`
+    #[arg(required = true)]
+    ids: Vec<String>,
+}
+
+impl Cli {
+    /// Collects the settings given on the command line or in the environment
+    fn overrides(&self) -> Profile {
+        Profile {
+            server_address: self.server_address.clone(),
+            token: self.token.clone(),
+            url_path: self.url_path.clone(),
+            threads: self.threads,
+            tpslimit: self.tpslimit,
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            segments: self.segments,
+            segment_threshold: self.segment_threshold,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            token_file: self.token_file.clone(),
+            hash_cache: self.hash_cache.clone(),
+            include: non_empty(&self.include),
+            exclude: non_empty(&self.exclude),
+            include_regex: non_empty(&self.include_regex),
+            exclude_regex: non_empty(&self.exclude_regex),
+            min_size: self.min_size.clone(),
+            max_size: self.max_size.clone(),
+            modified_after: self.modified_after.clone(),
+            modified_before: self.modified_before.clone(),
+            ..Profile::default()
+        }
+    }
+}
+
+/// Returns a repeatable flag's values, or `None` if it was not given so the
+/// profile's list applies
+fn non_empty(values: &[String]) -> Option<Vec<String>> {
+    (!values.is_empty()).then(|| values.to_vec())
+}
+
+/// Resolves a remote path given on the command line
+///
+/// # Arguments
+///
+/// * `base` - Directory relative paths start at, i.e. --url-path
+/// * `path` - The path as given
+fn resolve_remote(base: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", base.trim_end_matches('/'), path)
+    }
+}
+
+/// Previews and commits a batch or regex rename, or reverts the last one
+///
+/// The renames are checked against the directory listing and printed as a
+/// table before anything is sent. Committed renames are recorded in the
+/// journal for --undo.
+///
+/// # Arguments
+///
+/// * `client` - The Alist client
+/// * `dir` - Directory whose entries are renamed, `None` for --undo
+/// * `args` - The rename options
+async fn rename_many(
+    client: &AlistClient,
+    dir: Option<String>,
+    args: RenameManyArgs,
+) -> Result<()> {
+    let journal = args
+        .journal
+        .clone()
+        .or_else(config::default_rename_journal)
+        .map(RenameJournal::new)
+        .ok_or_else(|| {
+            anyhow!("--journal is required when neither XDG_CACHE_HOME nor HOME is set")
+        })?;
+    let server = client.config().server_address.clone();
+
+    if args.undo {
+        let batch = journal
+            .last(&server)
+            .await?
+            .ok_or_else(|| anyhow!("No renames on {} to undo", server))?;
+        let renames = batch.reverted();
+        let listing = client.list_dir(&batch.dir).await?;
+        check_renames(
+            listing.iter().map(|entry| entry.entry.name.as_str()),
+            &renames,
+        )?;
+        write_rename_preview(&mut std::io::stdout(), &batch.dir, &renames)?;
+        if args.dry_run || !(args.yes || confirm("Revert these renames?")?) {
+            return Ok(());
+        }
+        client.batch_rename(&batch.dir, renames).await?;
+        journal.forget(&batch).await?;
+        info!(
+            "Reverted {} renames made in {} at {}",
+            batch.renames.len(),
+            batch.dir,
+            batch.time
+        );
+        return Ok(());
+    }
+
+    let dir = dir.ok_or_else(|| anyhow!("A remote directory is required"))?;
+    let regex = args
+        .regex
+        .as_deref()
+        .map(|pattern| Regex::new(pattern).map_err(|e| anyhow!("Invalid --regex: {}", e)))
+        .transpose()?;
+    let replacement = args.replace.clone().unwrap_or_default();
+    let listing = client.list_dir(&dir).await?;
+    let names = || listing.iter().map(|entry| entry.entry.name.as_str());
+    let renames = match (&regex, &args.batch) {
+        (Some(regex), _) => regex_renames(names(), regex, &replacement),
+        (None, Some(batch)) => {
+            let content = if batch.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                fs::read_to_string(batch)
+                    .await
+                    .map_err(|e| anyhow!("Failed to read '{}': {}", batch.display(), e))?
+            };
+            parse_rename_list(&content)?
+        }
+        (None, None) => return Err(anyhow!("--regex or --batch is required")),
+    };
+    if renames.is_empty() {
+        info!("Nothing to rename in {}", dir);
+        return Ok(());
+    }
+    check_renames(names(), &renames)?;
+    write_rename_preview(&mut std::io::stdout(), &dir, &renames)?;
+    if args.dry_run || !(args.yes || confirm("Apply these renames?")?) {
+        return Ok(());
+    }
+
+    // Commit the previewed renames as a batch, even for --regex, so the
+    // server renames exactly what was confirmed and journaled
+    client.batch_rename(&dir, renames.clone()).await?;
+    let count = renames.len();
+    journal
+        .record(RenameBatch::new(&server, &dir, renames))
+        .await?;
+    info!(
+        "Renamed {} entries in {}, revert with rename --undo",
+        count, dir
+    );
+    Ok(())
+}
+
+/// Moves or copies the entries matching `transfer.sources`, one request per
+/// source directory
+async fn transfer(
+    client: &AlistClient,
+    url_path: &str,
+    transfer: TransferArgs,
+    copy: bool,
+    m_pb: MultiProgress,
+) -> Result<()> {
+    let sources: Vec<String> = transfer
+        .sources
+        .iter()
+        .map(|source| resolve_remote(url_path, source))
+        .collect();
+    let destination = resolve_remote(url_path, &transfer.destination);
+    let destination = match destination.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    let entries = client.expand_paths(&sources).await?;
+    let groups = api::manage::group_by_dir(entries.iter().map(|entry| entry.path_str.as_str()))?;
+    let mut started = Vec::new();
+    for (dir, names) in groups {
+        info!(
+            "{} {} from {} to {}",
+            if copy { "Copying" } else { "Moving" },
+            names.join(", "),
+            dir,
+            destination
+        );
+        let tasks = if copy {
+            client
+                .copy_entries(&dir, destination, names, transfer.overwrite)
+                .await?
+        } else {
+            client
+                .move_entries(&dir, destination, names, transfer.overwrite)
+                .await?
+        };
+        for task in &tasks {
+            info!("Server task {} started: {}", task.id, task.name);
+        }
+        started.extend(tasks);
+    }
+    if transfer.wait {
+        let kind = if copy { TaskKind::Copy } else { TaskKind::Move };
+        wait_for_started(client, kind, &started, m_pb).await?;
+    }
+    Ok(())
+}
+
+/// Time between polls while waiting for server tasks
+const TASK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Waits for the tasks a command started on the server
+///
+/// # Errors
+///
+/// Returns an error if a task cannot be looked up or did not succeed
+async fn wait_for_started(
+    client: &AlistClient,
+    kind: TaskKind,
+    tasks: &[TaskInfo],
+    m_pb: MultiProgress,
+) -> Result<()> {
+    if tasks.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<String> = tasks.iter().map(|task| task.id.clone()).collect();
+    let finished = client
+        .wait_for_tasks(kind, &ids, TASK_POLL_INTERVAL, m_pb)
+        .await?;
+    let failed: Vec<_> = finished
+        .iter()
+        .filter(|task| task.task_state() != TaskState::Succeeded)
+        .collect();
+    for task in &failed {
+        warn!(
+            "Server task {} {}: {} {}",
+            task.id,
+            task.task_state(),
+            task.name,
+            task.error
+        );
+    }
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "{} of {} {} tasks did not succeed",
+            failed.len(),
+            finished.len(),
+            kind
+        ));
+    }
+    info!("{} {} tasks succeeded", finished.len(), kind);
+    Ok(())
+}
+
+/// Logs the outcome of an upload
+///
+/// # Errors
+///
+/// Returns an error if any file failed to upload
+fn report_uploads(summary: &UploadSummary) -> Result<()> {
+    info!(
+        "Upload complete: {} uploaded, {} skipped, {} failed",
+        summary.uploaded.len(),
+        summary.skipped,
+        summary.failed.len()
+    );
+    for task in &summary.tasks {
+        info!("Server task {} started: {}", task.id, task.name);
+    }
+    if !summary.failed.is_empty() {
+        return Err(anyhow!(
+            "Upload completed with {} errors. See logs for details.",
+            summary.failed.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the password from standard input
+///
+/// A terminal does not echo the password; piped input is read as a line.
+fn prompt_password() -> Result<String> {
+    use std::io::{IsTerminal, Write};
+
+    if std::io::stdin().is_terminal() {
+        return Ok(rpassword::prompt_password("Password: ")?);
+    }
+    eprint!("Password: ");
+    std::io::stderr().flush()?;
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Asks a yes/no question on standard error, defaulting to no
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::Write;
+
+    eprint!("{question} [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Picks the local path from the command line, falling back to the profile
+fn resolve_local_path(local_path: Option<String>, fallback: Option<&String>) -> Result<String> {
+    local_path
+        .or_else(|| fallback.cloned())
+        .ok_or_else(|| anyhow!("--local-path is required unless the profile sets local_path"))
+}
+
+/// How `remove_noexist_files` disposes of files missing on the server
+struct PruneOptions {
+    /// Do the actual removal, otherwise only report
+    delete: bool,
+    /// Abort the run if more files than this would be removed
+    max_delete: Option<DeleteLimit>,
+    /// Move files here instead of deleting them
+    trash: Option<Trash>,
+    /// Only collect the files, leaving the local path untouched
+    dry_run: bool,
+}
+
+/// Resolves the trash directory, defaulting to one inside the local path
+fn trash_for(local_path: &str, trash_dir: Option<String>) -> Trash {
+    match trash_dir {
+        Some(dir) if !dir.is_empty() => Trash::new(dir),
+        _ => Trash::new(Path::new(local_path).join(DEFAULT_TRASH_DIR)),
+    }
+}
+
+/// Prunes local files whose remote counterpart no longer exists
+///
+/// # Returns
+///
+/// The files that were deleted or moved to the trash, or would be on a dry
+/// run
 async fn remove_noexist_files(
+    config: &Config,
     local_path: String,
     url_path: String,
     existing_files: &HashSet<String>,
-    delete: bool,
-) -> Result<()> {
+    failed_dirs: &[String],
+    prune: PruneOptions,
+) -> Result<Vec<PathBuf>> {
     // The realpath on the filesystem
     info!("Start to remove non-existent files");
     let folder_path = std::path::Path::new(&local_path).join(url_path.trim_start_matches('/'));
 
+    // Maps a local path back to the remote path it mirrors
+    let to_remote_path = |file_path: &Path| {
+        file_path
+            .strip_prefix(&local_path)
+            .ok()
+            .map(|rel_path| format!("/{}", rel_path.to_string_lossy()))
+    };
+    // Remote directories that failed to list have unknown contents, so nothing
+    // beneath them may be pruned
+    let is_protected = |remote_path: &str| {
+        failed_dirs
+            .iter()
+            .any(|dir| api::is_path_within(remote_path, dir))
+    };
+    // Files the filter leaves out are not part of the sync. Downloads keep the
+    // remote size and mtime, so the local file is checked against the size and
+    // date predicates. A .strm file is in scope if the media file it was made
+    // from would be; it has the media's mtime but not its size, so it is kept
+    // whenever sizes are filtered.
+    let in_scope = |entry: &walkdir::DirEntry, remote_path: &str| {
+        let Ok(metadata) = entry.metadata() else {
+            return false;
+        };
+        match remote_path.strip_suffix(".strm") {
+            Some(stem) => {
+                !config.filter.has_size_limits() &&
+                    config.classifier.strm_extensions().any(|ext| {
+                        config
+                            .filter
+                            .allows_local_file(&format!("{stem}.{ext}"), &metadata)
+                    })
+            }
+            None => config.filter.allows_local_file(remote_path, &metadata),
+        }
+    };
+
+    // Never walk into a trash, its files would look non-existent. The default
+    // one holds batches of earlier runs even when this run does not use it.
+    let trash_roots = [
+        Some(Path::new(&local_path).join(DEFAULT_TRASH_DIR)),
+        prune.trash.as_ref().map(|trash| trash.root().to_path_buf()),
+    ];
+    let not_trash = |entry: &walkdir::DirEntry| {
+        !trash_roots
+            .iter()
+            .flatten()
+            .any(|root| root == entry.path())
+    };
+    // State files live next to the mirrored files and are never pruned
+    let state_files = [
+        Snapshot::path_for(Path::new(&local_path)),
+        RunLock::path_for(Path::new(&local_path)),
+    ];
+
     trace!("folder_path {}", folder_path.display());
-    let iter = WalkDir::new(&folder_path)
+    let mut protected_files = 0usize;
+    let mut total_files = 0usize;
+    let candidates: Vec<_> = WalkDir::new(&folder_path)
         .into_iter()
+        .filter_entry(not_trash)
         .filter_map(|entry| entry.ok())
         .filter(|entry| entry.file_type().is_file()) // Only keep files
+        .filter(|entry| !state_files.iter().any(|file| file == entry.path()))
+        .filter(|entry| to_remote_path(entry.path()).is_none_or(|path| in_scope(entry, &path)))
         .filter(|entry| {
+            total_files += 1;
             // Keep only items whose file name is NOT in `existing_files`
             // (i.e., we want to remove them because they're "non-existent" remotely)
-            let file_path = entry.path();
-            let remote_path = match file_path.strip_prefix(&local_path) {
-                Ok(rel_path) => format!("/{}", rel_path.to_string_lossy()),
-                Err(_) => return true, // if strip_prefix fails, keep the file
+            let Some(remote_path) = to_remote_path(entry.path()) else {
+                return true; // if strip_prefix fails, keep the file
             };
+            if is_protected(&remote_path) {
+                protected_files += 1;
+                return false;
+            }
             !existing_files.contains(&remote_path)
-        });
+        })
+        .collect();
 
-    for entry in iter {
+    if let Some(limit) = prune.max_delete &&
+        limit.is_exceeded(candidates.len(), total_files)
+    {
+        let message = format!(
+            "Refusing to prune {} of {} local files, the limit is {}",
+            candidates.len(),
+            total_files,
+            limit
+        );
+        if prune.delete && !prune.dry_run {
+            return Err(anyhow!(message));
+        }
+        warn!("{}", message);
+    }
+    if prune.dry_run {
+        let planned = if prune.delete {
+            candidates
+                .iter()
+                .map(|entry| entry.path().to_path_buf())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        return Ok(planned);
+    }
+
+    let batch_dir = match &prune.trash {
+        Some(trash) if prune.delete && !candidates.is_empty() => Some(trash.new_batch().await?),
+        _ => None,
+    };
+    for entry in &candidates {
         info!(
             "Found non-existent Entry {}",
             entry.path().to_string_lossy()
         );
-        if delete {
-            fs::remove_file(entry.path()).await?;
+        if prune.delete {
+            match &batch_dir {
+                Some(batch_dir) => {
+                    Trash::move_file(batch_dir, Path::new(&local_path), entry.path()).await?
+                }
+                None => fs::remove_file(entry.path()).await?,
+            }
+        }
+    }
+    if prune.delete &&
+        !candidates.is_empty() &&
+        let Some(batch_dir) = &batch_dir
+    {
+        info!(
+            "Moved {} files to trash {}",
+            candidates.len(),
+            batch_dir.display()
+        );
+    }
+
+    if !failed_dirs.is_empty() {
+        warn!(
+            "Skipped {} local files under {} remote directories that failed to list",
+            protected_files,
+            failed_dirs.len()
+        );
+        for dir in failed_dirs {
+            warn!("  - {}", dir);
         }
     }
 
     for entry in WalkDir::new(&folder_path)
         .contents_first(true)
         .into_iter()
+        .filter_entry(not_trash)
         .filter_map(|entry| entry.ok())
         .filter(|entry| entry.file_type().is_dir())
+        .filter(|entry| !to_remote_path(entry.path()).is_some_and(|p| is_protected(&p)))
     {
         let file_path = entry.path();
         if tokio::fs::remove_dir(file_path).await.is_ok() {
@@ -117,28 +1031,406 @@ async fn remove_noexist_files(
         }
     }
 
+    let removed = if prune.delete {
+        candidates
+            .iter()
+            .map(|entry| entry.path().to_path_buf())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(removed)
+}
+
+/// Loads the selected profile from the config file and layers the command
+/// line and environment settings over it
+///
+/// # Errors
+///
+/// Returns an error if the config file or the profile cannot be loaded
+fn load_profile(path: Option<&Path>, name: Option<&str>, overrides: &Profile) -> Result<Profile> {
+    Ok(ConfigFile::discover(path)?
+        .profile(name)?
+        .merge(overrides.clone()))
+}
+
+/// Where `watch` reloads its settings from before every run
+struct ConfigSource<'a> {
+    /// Config file given with `--config`
+    path: Option<&'a Path>,
+    /// Profile given with `--profile`
+    profile: Option<&'a str>,
+    /// Settings from the command line and environment
+    overrides: &'a Profile,
+}
+
+/// When `watch` runs AutoSym
+enum Schedule {
+    /// Fixed delay between the end of one run and the start of the next
+    Interval(Duration),
+    /// At the times matched by a cron expression
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Returns how long to wait before the next run, or `None` if the cron
+    /// expression matches no future time
+    fn next_delay(&self) -> Option<Duration> {
+        match self {
+            Schedule::Interval(interval) => Some(*interval),
+            Schedule::Cron(schedule) => {
+                let next = schedule.upcoming(chrono::Local).next()?;
+                Some((next - chrono::Local::now()).to_std().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// First delay after a failed watch run, doubled on every further failure
+const WATCH_INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Longest delay between watch runs while the server is unhealthy
+const WATCH_MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// Runs AutoSym on a schedule until the process is stopped.
+///
+/// The config file is read again before every run and the client is rebuilt
+/// if the settings changed. A run is skipped while another process holds the
+/// lock on the local path. If the server does not answer `/ping` or a run
+/// fails, the next attempt is delayed with exponential backoff instead of
+/// waiting for the schedule.
+///
+/// # Errors
+///
+/// Returns an error if a new client cannot be built
+async fn watch(
+    source: ConfigSource<'_>,
+    mut profile: Profile,
+    mut client: AlistClient,
+    sync: &AutoSymArgs,
+    schedule: &Schedule,
+    m_pb: MultiProgress,
+) -> Result<()> {
+    let mut failures = 0u32;
+    loop {
+        match load_profile(source.path, source.profile, source.overrides) {
+            Ok(reloaded) if reloaded != profile => {
+                match reloaded.to_config().and_then(AlistClient::new) {
+                    Ok(reloaded_client) => {
+                        info!("Configuration changed, reloading");
+                        client = reloaded_client;
+                        profile = reloaded;
+                    }
+                    Err(e) => warn!("Invalid configuration, keeping the current one: {}", e),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Failed to reload configuration, keeping the current one: {}",
+                e
+            ),
+        }
+
+        let result = async {
+            client
+                .ping()
+                .await
+                .map_err(|e| anyhow!("Server is unhealthy: {}", e))?;
+            let local_path =
+                resolve_local_path(sync.local_path.clone(), profile.local_path.as_ref())?;
+            let url_path = profile.url_path.clone().unwrap_or_else(|| "/".to_string());
+            match RunLock::try_acquire(Path::new(&local_path))? {
+                Some(_lock) => {
+                    let media_servers = profile.media_servers.as_deref().unwrap_or_default();
+                    auto_sym(
+                        &client,
+                        url_path,
+                        local_path,
+                        sync,
+                        media_servers,
+                        None,
+                        m_pb.clone(),
+                    )
+                    .await
+                }
+                None => {
+                    info!("Another run is syncing '{}', skipping", local_path);
+                    Ok(())
+                }
+            }
+        }
+        .await;
+
+        let delay = match result {
+            Ok(()) => {
+                failures = 0;
+                let Some(delay) = schedule.next_delay() else {
+                    info!("No more scheduled runs");
+                    return Ok(());
+                };
+                delay
+            }
+            Err(e) => {
+                failures += 1;
+                let delay = WATCH_INITIAL_BACKOFF
+                    .saturating_mul(2u32.saturating_pow(failures - 1))
+                    .min(WATCH_MAX_BACKOFF);
+                warn!(
+                    "Sync failed ({} in a row): {}. Retrying in {}s",
+                    failures,
+                    e,
+                    delay.as_secs()
+                );
+                delay
+            }
+        };
+        info!("Next run in {}s", delay.as_secs());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Runs the AutoSym pipeline once: writes .strm and metadata files for the
+/// remote tree, saves the snapshot, prunes files gone from the server and
+/// asks the media servers to rescan what changed
+///
+/// With a `plan`, the changes are recorded in it instead and the local path
+/// is left untouched.
+async fn auto_sym(
+    client: &AlistClient,
+    url_path: String,
+    local_path: String,
+    sync: &AutoSymArgs,
+    media_servers: &[MediaServer],
+    plan: Option<&mut Plan>,
+    m_pb: MultiProgress,
+) -> Result<()> {
+    let strm_url = sync.strm_url;
+    let snapshot_path = Snapshot::path_for(Path::new(&local_path));
+    let previous = Arc::new(Snapshot::load(&snapshot_path).await?);
+    // A .strm file is only current if it was written with the same URL mode
+    let reuse_strm = previous.strm_url == Some(strm_url);
+    // Listings recorded under other filter rules lack entries now in scope
+    let rules = client.config().filter.rules();
+    let listings = if sync.full_scan || previous.filter != *rules {
+        Arc::new(Snapshot::new())
+    } else {
+        Arc::clone(&previous)
+    };
+    let is_current = |entry: &api::EntryWithPath, local_file: &Path| {
+        previous.is_unchanged(entry) && Path::new(&local_path).join(local_file).exists()
+    };
+
+    // Route files to the metadata, strm and download pipelines while the scan
+    // is running
+    let (metadata_tx, metadata_rx) = mpsc::channel(STREAM_BUFFER);
+    let (strm_tx, strm_rx) = mpsc::channel(STREAM_BUFFER);
+    let (download_tx, download_rx) = mpsc::channel(STREAM_BUFFER);
+    let entries =
+        client.stream_path_structure_incremental(url_path.clone(), listings, m_pb.clone());
+    let scan = async {
+        let (mut metadata_tx, mut strm_tx, mut download_tx) = (metadata_tx, strm_tx, download_tx);
+        let mut files_set = HashSet::new();
+        let mut failed_dirs = Vec::new();
+        let mut current = Snapshot::new();
+        current.strm_url = Some(strm_url);
+        current.filter = rules.clone();
+        let mut unchanged = 0usize;
+        // Files sent to be copied or downloaded, only recorded once fetched
+        let mut copies = Vec::new();
+        let mut downloads = Vec::new();
+        let mut entries = pin!(entries);
+        while let Some(result) = entries.next().await {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(err) => {
+                    failed_dirs.push(err.downcast::<api::ListDirError>()?.path);
+                    continue;
+                }
+            };
+            current.insert(entry.clone());
+            if entry.entry.is_dir {
+                continue;
+            }
+
+            // Build files_set: replace extension with "strm" for strm files, otherwise keep
+            // original
+            match client.config().classify(&entry.path_str) {
+                Some(FileAction::Strm) => {
+                    let path = Path::new(&entry.path_str).with_extension("strm");
+                    let local_file = path.strip_prefix("/").unwrap_or(&path);
+                    if reuse_strm && is_current(&entry, local_file) {
+                        unchanged += 1;
+                    } else {
+                        strm_tx.send(entry).await?;
+                    }
+                    files_set.insert(path.to_string_lossy().into_owned());
+                }
+                Some(action @ (FileAction::Copy | FileAction::Download)) => {
+                    files_set.insert(entry.path_str.clone());
+                    let local_file = Path::new(entry.path_str.trim_start_matches('/'));
+                    if is_current(&entry, local_file) {
+                        unchanged += 1;
+                    } else if action == FileAction::Copy {
+                        copies.push(entry.clone());
+                        metadata_tx.send(entry).await?;
+                    } else {
+                        downloads.push(entry.path_str.clone());
+                        download_tx.send(entry).await?;
+                    }
+                }
+                Some(FileAction::Ignore) | None => {
+                    files_set.insert(entry.path_str);
+                }
+            }
+        }
+        Ok::<_, anyhow::Error>((
+            files_set,
+            failed_dirs,
+            current,
+            unchanged,
+            copies,
+            downloads,
+        ))
+    };
+    let trash = sync.trash.clone().map(|dir| trash_for(&local_path, dir));
+    let prune = PruneOptions {
+        delete: sync.delete,
+        max_delete: sync.max_delete,
+        trash,
+        dry_run: plan.is_some(),
+    };
+
+    if let Some(plan) = plan {
+        // Collect the routed files instead of writing them
+        let collect = |rx: mpsc::Receiver<api::EntryWithPath>| async move {
+            Ok::<_, anyhow::Error>(rx.collect::<Vec<_>>().await)
+        };
+        let ((files_set, failed_dirs, _, unchanged, ..), metadata, strm, download) = tokio::try_join!(
+            scan,
+            collect(metadata_rx),
+            collect(strm_rx),
+            collect(download_rx),
+        )?;
+        info!("Skipped {} unchanged files", unchanged);
+
+        let local_root = Path::new(&local_path);
+        for entry in &strm {
+            let strm_file = local_root
+                .join(entry.path_str.trim_start_matches('/'))
+                .with_extension("strm");
+            let action = if strm_file.exists() {
+                PlanAction::UpdateStrm
+            } else {
+                PlanAction::CreateStrm
+            };
+            plan.push_entry(action, local_root, entry);
+        }
+        for entry in &metadata {
+            plan.push_entry(PlanAction::CopyMetadata, local_root, entry);
+        }
+        for entry in &download {
+            plan.push_download(local_root, entry).await;
+        }
+        let pruned = remove_noexist_files(
+            client.config(),
+            local_path,
+            url_path,
+            &files_set,
+            &failed_dirs,
+            prune,
+        )
+        .await?;
+        for file in pruned {
+            plan.push_prune(file);
+        }
+        return Ok(());
+    }
+
+    let (
+        (files_set, failed_dirs, mut current, unchanged, copies, downloads),
+        copied,
+        written,
+        downloaded,
+    ) = tokio::try_join!(
+        scan,
+        client.copy_metadata(metadata_rx, &local_path, m_pb.clone()),
+        client.create_strm_file(strm_rx, &local_path, strm_url, m_pb.clone()),
+        client.download_files(download_rx, &local_path, m_pb),
+    )?;
+    info!("Skipped {} unchanged files", unchanged);
+    if let Err(e) = client.save_hash_cache().await {
+        warn!("Failed to save the hash cache: {}", e);
+    }
+
+    // Directories that failed to list must be listed again next run
+    for dir in &failed_dirs {
+        current.forget_dir(dir);
+    }
+    // So must files that failed to fetch, or their old local copy would pass
+    // as current. Copies take the remote size and mtime once fetched, and
+    // every download that succeeded is reported, even if nothing was sent.
+    let local_file = |path: &str| Path::new(&local_path).join(path.trim_start_matches('/'));
+    for entry in &copies {
+        if !is_local_copy_current(&local_file(&entry.path_str), &entry.entry).await {
+            current.forget(&entry.path_str);
+        }
+    }
+    for path in downloads {
+        if !downloaded.contains(&local_file(&path)) {
+            current.forget(&path);
+        }
+    }
+    utils::ensure_parent_dir(&snapshot_path).await?;
+    current.save(&snapshot_path).await?;
+
+    let removed = remove_noexist_files(
+        client.config(),
+        local_path,
+        url_path,
+        &files_set,
+        &failed_dirs,
+        prune,
+    )
+    .await?;
+
+    // Tell the media servers which directories changed
+    if !media_servers.is_empty() {
+        let dirs = media_server::changed_dirs(
+            copied
+                .iter()
+                .chain(&written)
+                .chain(&downloaded)
+                .chain(&removed),
+        );
+        media_server::refresh_all(media_servers, client.http(), &dirs).await;
+    }
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Parse CLI arguments and initialize global CONFIG
+    // Parse CLI arguments, layer them over the config file profile and
+    // build the client
     let args = Cli::parse();
-    CONFIG
-        .set(Config {
-            server_address: args.server_address.clone(),
-            threads: args.threads,
-            token: args.token.clone(),
-            tpslimit: args.tpslimit,
-            concurrent_limit: std::cmp::max(args.threads, 10), // Min 10 for buffer_unordered operations
-            timeout: args.timeout,
-        })
-        .expect("CONFIG already initialized");
+    let overrides = args.overrides();
+    let profile = load_profile(args.config.as_deref(), args.profile.as_deref(), &overrides)?;
+    let client = AlistClient::new(profile.to_config()?)?;
+    let url_path = profile.url_path.clone().unwrap_or_else(|| "/".to_string());
 
     let m_pb = MultiProgress::new();
     // let wrapper = tracing_bridge::TracingWrapper::new(m_pb.clone());
 
-    let make_writer = MakeSuspendingWriter::new(std::io::stdout, m_pb.clone());
+    // A dry run prints its plan on stdout, keep the logs out of it
+    let prints_plan = matches!(
+        &args.command,
+        Commands::AutoSym { plan, .. } | Commands::Download { plan, .. } if plan.dry_run
+    );
+    let log_writer = if prints_plan {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let make_writer = MakeSuspendingWriter::new(log_writer, m_pb.clone());
     let fmt_layer = fmt::layer()
         .with_writer(make_writer)
         .with_ansi(true)
@@ -153,54 +1445,273 @@ async fn main() -> Result<()> {
     tracing::subscriber::set_global_default(subscriber)?;
 
     match args.command {
-        Commands::AutoSym { local_path, delete } => {
-            let client = std::sync::Arc::new(reqwest::Client::builder().no_proxy().build()?);
-            let res =
-                api::get_path_structure(args.url_path.clone(), m_pb.clone(), Arc::clone(&client))
+        Commands::AutoSym { sync, plan } => {
+            let local_path =
+                resolve_local_path(sync.local_path.clone(), profile.local_path.as_ref())?;
+            let media_servers = profile.media_servers.as_deref().unwrap_or_default();
+            if plan.dry_run {
+                let mut dry_run = Plan::default();
+                auto_sym(
+                    &client,
+                    url_path,
+                    local_path,
+                    &sync,
+                    media_servers,
+                    Some(&mut dry_run),
+                    m_pb,
+                )
+                .await?;
+                dry_run.write(&mut std::io::stdout(), plan.plan_format)?;
+            } else {
+                let _lock = RunLock::try_acquire(Path::new(&local_path))?
+                    .ok_or_else(|| anyhow!("Another run is already syncing '{}'", local_path))?;
+                auto_sym(
+                    &client,
+                    url_path,
+                    local_path,
+                    &sync,
+                    media_servers,
+                    None,
+                    m_pb,
+                )
+                .await?;
+            }
+        }
+        Commands::Watch {
+            sync,
+            interval,
+            cron,
+        } => {
+            let schedule = match cron {
+                Some(expression) => {
+                    Schedule::Cron(Box::new(expression.parse().map_err(|e| {
+                        anyhow!("Invalid cron expression '{}': {}", expression, e)
+                    })?))
+                }
+                None => Schedule::Interval(Duration::from_secs(interval)),
+            };
+            let config_source = ConfigSource {
+                path: args.config.as_deref(),
+                profile: args.profile.as_deref(),
+                overrides: &overrides,
+            };
+            watch(config_source, profile, client, &sync, &schedule, m_pb).await?;
+        }
+        Commands::Download { local_path, plan } => {
+            let local_path = resolve_local_path(
+                local_path,
+                profile
+                    .download_path
+                    .as_ref()
+                    .or(profile.local_path.as_ref()),
+            )?;
+            if plan.dry_run {
+                client
+                    .plan_download(url_path, &local_path, m_pb)
+                    .await
+                    .write(&mut std::io::stdout(), plan.plan_format)?;
+            } else {
+                let result = client.download_folders(url_path, &local_path, m_pb).await;
+                // Digests of the files verified so far stay useful after a failure
+                if let Err(e) = client.save_hash_cache().await {
+                    warn!("Failed to save the hash cache: {}", e);
+                }
+                result?;
+            }
+        }
+        Commands::Upload {
+            local_path,
+            overwrite,
+            upload,
+        } => {
+            let summary = client
+                .upload_path(
+                    &local_path,
+                    &url_path,
+                    upload.options(overwrite),
+                    m_pb.clone(),
+                )
+                .await;
+            if let Err(e) = client.save_hash_cache().await {
+                warn!("Failed to save the hash cache: {}", e);
+            }
+            let summary = summary?;
+            report_uploads(&summary)?;
+            if upload.wait {
+                wait_for_started(&client, TaskKind::Upload, &summary.tasks, m_pb).await?;
+            }
+        }
+        Commands::MirrorUp {
+            local_path,
+            compare,
+            delete,
+            max_delete,
+            upload,
+        } => {
+            let options = MirrorOptions {
+                upload: upload.options(OverwritePolicy::Overwrite),
+                compare,
+                delete,
+                max_delete,
+            };
+            let summary = client
+                .mirror_up(&local_path, &url_path, options, m_pb.clone())
+                .await;
+            if let Err(e) = client.save_hash_cache().await {
+                warn!("Failed to save the hash cache: {}", e);
+            }
+            let summary = summary?;
+            info!(
+                "Mirror complete: {} unchanged, {} deleted",
+                summary.unchanged,
+                summary.deleted.len()
+            );
+            report_uploads(&summary.upload)?;
+            if upload.wait {
+                wait_for_started(&client, TaskKind::Upload, &summary.upload.tasks, m_pb).await?;
+            }
+            if !summary.failed_deletes.is_empty() {
+                return Err(anyhow!(
+                    "Failed to delete {} remote files. See logs for details.",
+                    summary.failed_deletes.len()
+                ));
+            }
+        }
+        Commands::Mkdir { path } => {
+            let path = resolve_remote(&url_path, &path);
+            client.mkdir(&path).await?;
+            info!("Created {}", path);
+        }
+        Commands::Rename {
+            path,
+            name,
+            overwrite,
+            many,
+        } => {
+            let path = path.map(|path| resolve_remote(&url_path, &path));
+            match (path, name) {
+                (path, _) if many.is_set() => rename_many(&client, path, many).await?,
+                (Some(path), Some(name)) => {
+                    client.rename(&path, &name, overwrite).await?;
+                    info!("Renamed {} to {}", path, name);
+                }
+                _ => return Err(anyhow!("A path and a new name are required")),
+            }
+        }
+        Commands::Mv { transfer: args } => transfer(&client, &url_path, args, false, m_pb).await?,
+        Commands::Cp { transfer: args } => transfer(&client, &url_path, args, true, m_pb).await?,
+        Commands::Rm { paths, recursive } => {
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|path| resolve_remote(&url_path, path))
+                .collect();
+            let entries = client.expand_paths(&paths).await?;
+            if !recursive && let Some(dir) = entries.iter().find(|entry| entry.entry.is_dir) {
+                return Err(anyhow!(
+                    "'{}' is a directory, use --recursive to remove it",
+                    dir.path_str
+                ));
+            }
+            let groups =
+                api::manage::group_by_dir(entries.iter().map(|entry| entry.path_str.as_str()))?;
+            for (dir, names) in groups {
+                info!("Removing {} from {}", names.join(", "), dir);
+                client.remove_entries(&dir, names).await?;
+            }
+        }
+        Commands::Tasks { action } => match action {
+            TaskAction::List { done, queues } => {
+                let tasks = client.list_tasks_of(&queues.kinds(), done).await?;
+                write_task_table(&mut std::io::stdout(), &tasks)?;
+            }
+            TaskAction::Watch { interval, queues } => {
+                let finished = client
+                    .watch_tasks(&queues.kinds(), Duration::from_secs(interval.max(1)), m_pb)
                     .await?;
-
-            // Single pass: collect files with extensions AND build the final files_set
-            let mut files_with_ext: Vec<(String, &api::EntryWithPath)> = Vec::new();
-            let mut files_set = HashSet::with_capacity(res.len());
-
-            for entry in &res {
-                if entry.entry.is_dir {
-                    continue;
+                let failed = finished
+                    .iter()
+                    .filter(|(_, task)| task.task_state() != TaskState::Succeeded)
+                    .count();
+                info!(
+                    "No tasks left, {} finished while watching, {} did not succeed",
+                    finished.len(),
+                    failed
+                );
+            }
+            TaskAction::Cancel { tasks } => {
+                for id in &tasks.ids {
+                    client.cancel_task(tasks.kind, id).await?;
+                    info!("Canceled {} task {}", tasks.kind, id);
                 }
-
-                let path = Path::new(&entry.path_str);
-
-                // Extract extension and add to files_with_ext if present
-                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                    files_with_ext.push((ext.to_owned(), entry));
-
-                    // Build files_set: replace extension with "strm" if streamable, otherwise keep
-                    // original
-                    let final_path = if api::FILE_STRM.contains(&ext) {
-                        path.with_extension("strm").to_string_lossy().into_owned()
-                    } else {
-                        entry.path_str.clone()
-                    };
-                    files_set.insert(final_path);
-                } else {
-                    // No extension, just add the path as-is
-                    files_set.insert(entry.path_str.clone());
+            }
+            TaskAction::Retry { tasks } => {
+                for id in &tasks.ids {
+                    client.retry_task(tasks.kind, id).await?;
+                    info!("Retrying {} task {}", tasks.kind, id);
                 }
             }
-
-            api::copy_metadata(
-                &files_with_ext,
-                &local_path,
-                m_pb.clone(),
-                Arc::clone(&client),
-            )
-            .await?;
-            api::create_strm_file(&files_with_ext, &local_path, m_pb, client).await?;
-
-            remove_noexist_files(local_path, args.url_path, &files_set, delete).await?;
+            TaskAction::ClearDone { queues } => {
+                let cleared = client.clear_done_tasks_of(&queues.kinds()).await?;
+                info!("Cleared the finished tasks of {} queues", cleared);
+            }
+        },
+        Commands::Login { otp, plain } => {
+            let config = client.config();
+            let username = config
+                .username
+                .clone()
+                .ok_or_else(|| anyhow!("--username is required to log in"))?;
+            let password = match config.password.clone() {
+                Some(password) => password,
+                None => prompt_password()?,
+            };
+            let token = client
+                .login(&username, &password, otp.as_deref(), !plain)
+                .await?;
+            match &config.token_file {
+                Some(path) => {
+                    api::auth::save_token(path, &token).await?;
+                    info!(
+                        "Logged in as {}, token saved to {}",
+                        username,
+                        path.display()
+                    );
+                }
+                None => println!("{token}"),
+            }
         }
-        Commands::Download { local_path } => {
-            download::download_folders(args.url_path, &local_path, m_pb).await?;
+        Commands::Trash {
+            local_path,
+            trash_dir,
+            action,
+        } => {
+            let local_path = resolve_local_path(local_path, profile.local_path.as_ref())?;
+            let trash = trash_for(&local_path, trash_dir);
+            match action {
+                TrashAction::List => {
+                    for batch in trash.batches().await? {
+                        println!("{batch}");
+                    }
+                }
+                TrashAction::Restore { batch } => {
+                    let batch = match batch {
+                        Some(batch) => batch,
+                        None => trash
+                            .batches()
+                            .await?
+                            .pop()
+                            .ok_or_else(|| anyhow!("Trash is empty"))?,
+                    };
+                    let restored = trash.restore(&batch, Path::new(&local_path)).await?;
+                    info!("Restored {} files from trash batch {}", restored, batch);
+                }
+                TrashAction::Purge { older_than_days } => {
+                    let purged = trash
+                        .purge(older_than_days.map(chrono::Duration::days))
+                        .await?;
+                    info!("Purged {} trash batches", purged.len());
+                }
+            }
         }
     }
 
@@ -0,0 +1,234 @@
+//! Library refresh notifications for Jellyfin, Emby and Plex.
+//!
+//! Media servers are configured per profile:
+//!
+//! ```toml
+//! [[profiles.home.media_servers]]
+//! kind = "jellyfin"
+//! url = "http://192.168.0.10:8096"
+//! token = "api-key"
+//! # The strm library is mounted at another path inside the container
+//! local_prefix = "/media/strm"
+//! server_prefix = "/data/strm"
+//! ```
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// Above this many changed directories a full library scan is requested
+/// instead of one per directory
+pub const MAX_SCOPED_PATHS: usize = 100;
+
+/// Timeout for a single refresh request in seconds
+const REQUEST_TIMEOUT: u64 = 30;
+
+/// Media server software
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaServerKind {
+    Jellyfin,
+    Emby,
+    Plex,
+}
+
+/// A media server to notify after AutoSym changed the library
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MediaServer {
+    pub kind: MediaServerKind,
+    /// Base address, e.g. `http://192.168.0.10:8096`
+    pub url: String,
+    /// API key (Jellyfin, Emby) or `X-Plex-Token` (Plex)
+    pub token: String,
+    /// Plex library section id; without it Plex rescans every section
+    pub section: Option<String>,
+    /// Local path prefix replaced by `server_prefix` when the media server
+    /// sees the library under a different path
+    pub local_prefix: Option<PathBuf>,
+    pub server_prefix: Option<PathBuf>,
+}
+
+/// One entry of the Jellyfin/Emby `Library/Media/Updated` request
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MediaUpdate {
+    path: String,
+    update_type: &'static str,
+}
+
+/// Body of the Jellyfin/Emby `Library/Media/Updated` request
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MediaUpdatedRequest {
+    updates: Vec<MediaUpdate>,
+}
+
+/// Reduces changed files to the directories containing them.
+///
+/// Directories removed by pruning are replaced by their closest existing
+/// ancestor, so the media server rescans the folder that lost them. Relative
+/// paths are made absolute, the media server cannot resolve them.
+///
+/// # Arguments
+///
+/// * `files` - Local files that were written or removed
+///
+/// # Returns
+///
+/// The distinct absolute directories to refresh
+pub fn changed_dirs<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> BTreeSet<PathBuf> {
+    files
+        .into_iter()
+        .filter_map(|file| std::path::absolute(file).ok())
+        .filter_map(|file| {
+            file.ancestors()
+                .skip(1)
+                .find(|dir| dir.is_dir())
+                .map(Path::to_path_buf)
+        })
+        .collect()
+}
+
+impl MediaServer {
+    /// Maps a local directory to the path the media server sees
+    ///
+    /// A relative `local_prefix` is taken from the current directory, like
+    /// the paths from [`changed_dirs`].
+    pub fn server_path(&self, local: &Path) -> PathBuf {
+        match (&self.local_prefix, &self.server_prefix) {
+            (Some(local_prefix), Some(server_prefix)) => {
+                let local_prefix =
+                    std::path::absolute(local_prefix).unwrap_or_else(|_| local_prefix.clone());
+                match local.strip_prefix(local_prefix) {
+                    Ok(rest) if rest.as_os_str().is_empty() => server_prefix.clone(),
+                    Ok(rest) => server_prefix.join(rest),
+                    Err(_) => local.to_path_buf(),
+                }
+            }
+            _ => local.to_path_buf(),
+        }
+    }
+
+    /// Attaches the token the way this server expects it
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.kind {
+            MediaServerKind::Jellyfin | MediaServerKind::Emby => {
+                request.header("X-Emby-Token", &self.token)
+            }
+            MediaServerKind::Plex => request.header("X-Plex-Token", &self.token),
+        }
+    }
+
+    /// Sends one refresh request
+    async fn send(&self, request: RequestBuilder) -> Result<()> {
+        let response = self
+            .authorize(request)
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("{} returned HTTP {}", self.url, response.status()));
+        }
+        Ok(())
+    }
+
+    /// Asks the media server to rescan the changed directories.
+    ///
+    /// Jellyfin and Emby get one `Library/Media/Updated` request listing every
+    /// directory, Plex gets a partial scan per directory of the configured
+    /// section. Without a section, or with more than [`MAX_SCOPED_PATHS`]
+    /// directories, the whole library is rescanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `http` - HTTP client for the requests
+    /// * `dirs` - Local directories whose contents changed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails
+    pub async fn refresh(&self, http: &Client, dirs: &BTreeSet<PathBuf>) -> Result<()> {
+        if dirs.is_empty() {
+            return Ok(());
+        }
+        let base = self.url.trim_end_matches('/');
+        let scoped = dirs.len() <= MAX_SCOPED_PATHS;
+        let paths = || dirs.iter().map(|dir| self.server_path(dir));
+
+        match (self.kind, scoped, &self.section) {
+            (MediaServerKind::Jellyfin | MediaServerKind::Emby, true, _) => {
+                let prefix = if self.kind == MediaServerKind::Emby {
+                    "/emby"
+                } else {
+                    ""
+                };
+                let body = MediaUpdatedRequest {
+                    updates: paths()
+                        .map(|path| MediaUpdate {
+                            path: path.to_string_lossy().into_owned(),
+                            update_type: "Modified",
+                        })
+                        .collect(),
+                };
+                self.send(
+                    http.post(format!("{base}{prefix}/Library/Media/Updated"))
+                        .json(&body),
+                )
+                .await?;
+            }
+            (MediaServerKind::Jellyfin, false, _) => {
+                self.send(http.post(format!("{base}/Library/Refresh")))
+                    .await?;
+            }
+            (MediaServerKind::Emby, false, _) => {
+                self.send(http.post(format!("{base}/emby/Library/Refresh")))
+                    .await?;
+            }
+            (MediaServerKind::Plex, true, Some(section)) => {
+                for path in paths() {
+                    debug!("Refreshing Plex section {} at {}", section, path.display());
+                    self.send(
+                        http.get(format!("{base}/library/sections/{section}/refresh"))
+                            .query(&[("path", path.to_string_lossy())]),
+                    )
+                    .await?;
+                }
+            }
+            (MediaServerKind::Plex, _, section) => {
+                let section = section.as_deref().unwrap_or("all");
+                self.send(http.get(format!("{base}/library/sections/{section}/refresh")))
+                    .await?;
+            }
+        }
+
+        if scoped {
+            info!("Asked {} to rescan {} directories", self.url, dirs.len());
+        } else {
+            info!("Asked {} to rescan the library", self.url);
+        }
+        Ok(())
+    }
+}
+
+/// Notifies every media server, logging failures without aborting
+///
+/// # Arguments
+///
+/// * `servers` - Media servers to notify
+/// * `http` - HTTP client for the requests
+/// * `dirs` - Local directories whose contents changed
+pub async fn refresh_all(servers: &[MediaServer], http: &Client, dirs: &BTreeSet<PathBuf>) {
+    for server in servers {
+        if let Err(e) = server.refresh(http, dirs).await {
+            warn!("Failed to refresh media server {}: {}", server.url, e);
+        }
+    }
+}
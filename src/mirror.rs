@@ -0,0 +1,239 @@
+//! Local-to-remote sync, the reverse of Download.
+//!
+//! A local directory is compared against the remote tree and only new or
+//! changed files are uploaded. Remote files that no longer exist locally can
+//! be deleted.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use futures::{StreamExt, stream};
+use indicatif::MultiProgress;
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::{AlistClient, manage::group_by_dir, types::EntryWithPath},
+    upload::{OverwritePolicy, UploadOptions, UploadSummary, upload_targets},
+    utils::{file_ops::provider_checksum, trash::DeleteLimit},
+};
+
+/// How a remote file is judged to match the local one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Compare {
+    /// Same size
+    #[default]
+    Size,
+    /// Same size and, where the storage reports one, the same hash
+    Hash,
+}
+
+/// How a mirror run behaves
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MirrorOptions {
+    /// Endpoint and headers of the uploads; files are always overwritten
+    pub upload: UploadOptions,
+    pub compare: Compare,
+    /// Delete remote files that no longer exist locally
+    pub delete: bool,
+    /// Abort before deleting more remote files than this
+    pub max_delete: Option<DeleteLimit>,
+}
+
+/// Outcome of [`AlistClient::mirror_up`]
+#[derive(Debug, Default)]
+pub struct MirrorSummary {
+    pub upload: UploadSummary,
+    /// Number of files already current on the server
+    pub unchanged: usize,
+    /// Remote files that no longer exist locally
+    pub extraneous: Vec<String>,
+    /// Remote files that were deleted
+    pub deleted: Vec<String>,
+    /// Remote files that could not be deleted
+    pub failed_deletes: Vec<String>,
+}
+
+impl AlistClient {
+    /// Uploads the new and changed files of a local directory
+    ///
+    /// Local files are matched to remote ones by their path relative to
+    /// `local_path` and `remote_dir`. Remote directories that could not be
+    /// listed are treated as empty, so their files are uploaded again but
+    /// never deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_path` - Local directory to mirror
+    /// * `remote_dir` - Remote directory to bring up to date
+    /// * `options` - Comparison, upload and deletion settings
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// What was uploaded, kept and deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `local_path` is not a directory, the remote tree
+    /// cannot be listed, or more files would be deleted than `max_delete`
+    /// allows. Individual upload and delete failures are collected in the
+    /// summary.
+    pub async fn mirror_up(
+        &self,
+        local_path: &Path,
+        remote_dir: &str,
+        options: MirrorOptions,
+        m_pb: MultiProgress,
+    ) -> Result<MirrorSummary> {
+        if !local_path.is_dir() {
+            return Err(anyhow!("'{}' is not a directory", local_path.display()));
+        }
+        let local_files = upload_targets(local_path, remote_dir)?;
+        let structure = self
+            .get_path_structure(remote_dir.to_string(), m_pb.clone())
+            .await?;
+        let mut remote: HashMap<String, EntryWithPath> = structure
+            .entries
+            .into_iter()
+            .filter(|entry| !entry.entry.is_dir)
+            .map(|entry| (entry.path_str.clone(), entry))
+            .collect();
+
+        // Files filtered out locally must not count as deleted
+        let local_paths: HashSet<String> = local_files
+            .iter()
+            .map(|(_, remote_path)| remote_path.clone())
+            .collect();
+        let mut extraneous: Vec<String> = remote
+            .keys()
+            .filter(|path| !local_paths.contains(*path))
+            .cloned()
+            .collect();
+        extraneous.sort();
+
+        if options.delete &&
+            let Some(limit) = options.max_delete &&
+            limit.is_exceeded(extraneous.len(), remote.len())
+        {
+            return Err(anyhow!(
+                "Refusing to delete {} of {} remote files, the limit is {}",
+                extraneous.len(),
+                remote.len(),
+                limit
+            ));
+        }
+
+        let filter = &self.config().filter;
+        let candidates = local_files.into_iter().filter(|(local, remote_path)| {
+            std::fs::metadata(local)
+                .is_ok_and(|metadata| filter.allows_local_file(remote_path, &metadata))
+        });
+
+        // Compare concurrently, hashing may read whole files
+        let mut checks = stream::iter(candidates)
+            .map(|(local, remote_path)| {
+                let entry = remote.remove(&remote_path);
+                let m_pb = m_pb.clone();
+                async move {
+                    let current = match &entry {
+                        Some(entry) => self
+                            .is_remote_current(&local, entry, options.compare, m_pb)
+                            .await
+                            .unwrap_or_else(|e| {
+                                warn!("Failed to compare {}: {}", local.display(), e);
+                                false
+                            }),
+                        None => false,
+                    };
+                    (local, remote_path, current)
+                }
+            })
+            .buffer_unordered(self.config().threads.max(1));
+
+        let mut summary = MirrorSummary::default();
+        let mut to_upload = Vec::new();
+        while let Some((local, remote_path, current)) = checks.next().await {
+            if current {
+                summary.unchanged += 1;
+            } else {
+                to_upload.push((local, remote_path));
+            }
+        }
+        drop(checks);
+        info!(
+            "{} files up to date, {} to upload",
+            summary.unchanged,
+            to_upload.len()
+        );
+
+        to_upload.sort();
+        let upload = UploadOptions {
+            overwrite: OverwritePolicy::Overwrite,
+            ..options.upload
+        };
+        summary.upload = self.upload_files(to_upload, upload, m_pb).await;
+
+        if !options.delete {
+            if !extraneous.is_empty() {
+                info!(
+                    "{} remote files no longer exist locally, delete them with --delete",
+                    extraneous.len()
+                );
+            }
+            summary.extraneous = extraneous;
+            return Ok(summary);
+        }
+
+        // One request per remote directory
+        for (dir, names) in group_by_dir(extraneous.iter().map(String::as_str))? {
+            let paths = names
+                .iter()
+                .map(|name| format!("{}/{}", dir.trim_end_matches('/'), name));
+            match self.remove_entries(&dir, names.clone()).await {
+                Ok(()) => summary.deleted.extend(paths),
+                Err(e) => {
+                    warn!("Failed to delete {} files in {}: {}", names.len(), dir, e);
+                    summary.failed_deletes.extend(paths);
+                }
+            }
+        }
+        debug!("Deleted {:?}", summary.deleted);
+        summary.extraneous = extraneous;
+        Ok(summary)
+    }
+
+    /// Checks if a remote file matches a local one
+    ///
+    /// # Arguments
+    ///
+    /// * `local_path` - The local file
+    /// * `entry` - The remote file
+    /// * `compare` - Whether hashes are compared as well as sizes
+    /// * `m_pb` - Multi-progress bar for hashing feedback
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the local file cannot be read
+    async fn is_remote_current(
+        &self,
+        local_path: &Path,
+        entry: &EntryWithPath,
+        compare: Compare,
+        m_pb: MultiProgress,
+    ) -> Result<bool> {
+        let size = tokio::fs::metadata(local_path).await?.len();
+        if size != entry.entry.size {
+            return Ok(false);
+        }
+        match &entry.entry.hash_info {
+            Some(hash) if compare == Compare::Hash && provider_checksum(entry) => {
+                self.verify_checksum(hash, local_path, m_pb).await
+            }
+            _ => Ok(true),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Dry-run plans listing what AutoSym or Download would change on disk.
+
+use std::{
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    api::types::EntryWithPath,
+    utils::{file_ops::is_local_copy_current, provider_checksum},
+};
+
+/// How a plan is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PlanFormat {
+    /// Aligned columns for reading
+    #[default]
+    Table,
+    /// A JSON document for scripts
+    Json,
+}
+
+/// Change a run would make to one local file
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    /// Write a .strm file that does not exist yet
+    CreateStrm,
+    /// Rewrite an existing .strm file
+    UpdateStrm,
+    /// Copy a metadata file
+    CopyMetadata,
+    /// Download a file in full
+    Download,
+    /// Verify the checksum of a local file with the remote size, and
+    /// download the file only if it differs
+    Verify,
+    /// Delete or trash a file missing on the server
+    Prune,
+}
+
+impl fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlanAction::CreateStrm => "create strm",
+            PlanAction::UpdateStrm => "update strm",
+            PlanAction::CopyMetadata => "copy metadata",
+            PlanAction::Download => "download",
+            PlanAction::Verify => "verify",
+            PlanAction::Prune => "prune",
+        })
+    }
+}
+
+/// One planned change
+#[derive(Serialize, Debug, Clone)]
+pub struct PlanItem {
+    pub action: PlanAction,
+    /// Local file that would be written or removed
+    pub local_path: PathBuf,
+    /// Remote file it comes from, `None` for pruned files
+    pub remote_path: Option<String>,
+    /// Size of the remote file, or of the local file for pruned files
+    pub size: Option<u64>,
+}
+
+/// Every change a run would make
+#[derive(Serialize, Debug, Default)]
+pub struct Plan {
+    pub items: Vec<PlanItem>,
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+impl Plan {
+    /// Records a change for a remote file
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The planned change
+    /// * `local_root` - Local directory mirroring the remote root
+    /// * `entry` - The remote file
+    pub fn push_entry(&mut self, action: PlanAction, local_root: &Path, entry: &EntryWithPath) {
+        let mut local_path = local_root.join(entry.path_str.trim_start_matches('/'));
+        if matches!(action, PlanAction::CreateStrm | PlanAction::UpdateStrm) {
+            local_path.set_extension("strm");
+        }
+        self.items.push(PlanItem {
+            action,
+            local_path,
+            remote_path: Some(entry.path_str.clone()),
+            size: Some(entry.entry.size),
+        });
+    }
+
+    /// Records the download of a remote file, unless its local copy has the
+    /// remote size and mtime
+    ///
+    /// A local copy with the remote size but another mtime is recorded as
+    /// [`PlanAction::Verify`] if the provider has a checksum, since the
+    /// download is skipped when the checksum matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_root` - Local directory mirroring the remote root
+    /// * `entry` - The remote file
+    pub async fn push_download(&mut self, local_root: &Path, entry: &EntryWithPath) {
+        let local_path = local_root.join(entry.path_str.trim_start_matches('/'));
+        if is_local_copy_current(&local_path, &entry.entry).await {
+            return;
+        }
+        let verifiable = entry.entry.hash_info.is_some() &&
+            provider_checksum(entry) &&
+            tokio::fs::metadata(&local_path)
+                .await
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() == entry.entry.size);
+        let action = if verifiable {
+            PlanAction::Verify
+        } else {
+            PlanAction::Download
+        };
+        self.push_entry(action, local_root, entry);
+    }
+
+    /// Records a local file that would be pruned
+    pub fn push_prune(&mut self, local_path: PathBuf) {
+        let size = std::fs::metadata(&local_path).ok().map(|m| m.len());
+        self.items.push(PlanItem {
+            action: PlanAction::Prune,
+            local_path,
+            remote_path: None,
+            size,
+        });
+    }
+
+    /// Returns the number of planned changes of one kind
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.action == action)
+            .count()
+    }
+
+    /// Writes the plan, sorted by local path
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Where to write the plan
+    /// * `format` - Table or JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails
+    pub fn write(&mut self, out: &mut impl Write, format: PlanFormat) -> Result<()> {
+        self.items.sort_by(|a, b| a.local_path.cmp(&b.local_path));
+        match format {
+            PlanFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+            PlanFormat::Table => {
+                writeln!(out, "{:<13}  {:>10}  PATH", "ACTION", "SIZE")?;
+                for item in &self.items {
+                    let size = item.size.map_or_else(|| "-".to_string(), format_size);
+                    writeln!(
+                        out,
+                        "{:<13}  {:>10}  {}",
+                        item.action.to_string(),
+                        size,
+                        item.local_path.display()
+                    )?;
+                }
+                let summary: Vec<_> = [
+                    PlanAction::CreateStrm,
+                    PlanAction::UpdateStrm,
+                    PlanAction::CopyMetadata,
+                    PlanAction::Download,
+                    PlanAction::Verify,
+                    PlanAction::Prune,
+                ]
+                .into_iter()
+                .filter_map(|action| {
+                    let count = self.count(action);
+                    (count > 0).then(|| format!("{count} {action}"))
+                })
+                .collect();
+                if summary.is_empty() {
+                    writeln!(out, "Nothing to do")?;
+                } else {
+                    writeln!(out, "Plan: {}", summary.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Persistent snapshot of the remote tree for incremental AutoSym runs.
+//!
+//! The snapshot records every directory listing of the last scan, keyed by
+//! the remote directory path, together with the `modified` timestamp the
+//! parent listing reported for that directory. A directory whose timestamp is
+//! unchanged is not listed again, and entries whose size, timestamp, hash,
+//! sign and provider are unchanged are not rewritten.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    api::types::{EntryWithPath, StrmUrlMode},
+    filter::FilterRules,
+    utils::file_ops::write_atomic,
+};
+
+/// Snapshot file name, created inside the local path
+pub const SNAPSHOT_FILE: &str = ".alist_snapshot.json";
+
+/// Format version, bumped when the layout changes incompatibly
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Remote tree as seen by the last AutoSym run
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Snapshot {
+    version: u32,
+    /// URL mode the .strm files were written with
+    pub strm_url: Option<StrmUrlMode>,
+    /// Filter the listings were recorded with; they lack the entries it
+    /// rejected
+    #[serde(default)]
+    pub filter: FilterRules,
+    /// Listings by remote directory path
+    dirs: HashMap<String, DirListing>,
+}
+
+/// One directory listing
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct DirListing {
+    /// `modified` of the directory as reported by its parent listing, `None`
+    /// for the scan root
+    modified: Option<String>,
+    /// Entries by name
+    entries: BTreeMap<String, EntryWithPath>,
+}
+
+/// Returns the remote directory containing `path`
+fn parent_dir(path: &str) -> &str {
+    match path.rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    }
+}
+
+/// Returns the name of the remote entry at `path`
+fn entry_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+impl Snapshot {
+    /// Returns the snapshot location for a local path
+    pub fn path_for(local_path: &Path) -> PathBuf {
+        local_path.join(SNAPSHOT_FILE)
+    }
+
+    /// Loads a snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Snapshot file
+    ///
+    /// # Returns
+    ///
+    /// The snapshot, or an empty one if the file does not exist or was
+    /// written by an incompatible version
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(anyhow!("Failed to read '{}': {}", path.display(), e)),
+        };
+        let snapshot: Self = serde_json::from_slice(&content)
+            .map_err(|e| anyhow!("Invalid snapshot '{}': {}", path.display(), e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Ok(Self::new());
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot, replacing the file atomically
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec(self)?).await
+    }
+
+    /// Creates an empty snapshot
+    pub fn new() -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the previous listing of a directory if it is still current
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Remote directory path
+    /// * `modified` - Current `modified` of the directory
+    ///
+    /// # Returns
+    ///
+    /// The recorded entries, or `None` if the directory was not recorded or
+    /// its timestamp changed
+    pub fn listing<'a>(
+        &'a self,
+        dir: &str,
+        modified: &str,
+    ) -> Option<impl Iterator<Item = &'a EntryWithPath> + use<'a>> {
+        self.dirs
+            .get(dir)
+            .filter(|listing| listing.modified.as_deref() == Some(modified))
+            .map(|listing| listing.entries.values())
+    }
+
+    /// Returns the recorded entry at a remote path
+    pub fn get(&self, path: &str) -> Option<&EntryWithPath> {
+        self.dirs
+            .get(parent_dir(path))?
+            .entries
+            .get(entry_name(path))
+    }
+
+    /// Checks if an entry is recorded with the same size, timestamp, hash,
+    /// sign and provider
+    pub fn is_unchanged(&self, entry: &EntryWithPath) -> bool {
+        self.get(&entry.path_str).is_some_and(|previous| {
+            previous.entry.size == entry.entry.size &&
+                previous.entry.modified == entry.entry.modified &&
+                previous.entry.hash_info == entry.entry.hash_info &&
+                previous.entry.sign == entry.entry.sign &&
+                previous.provider == entry.provider
+        })
+    }
+
+    /// Records an entry found by the scan
+    pub fn insert(&mut self, entry: EntryWithPath) {
+        if entry.entry.is_dir {
+            self.dirs
+                .entry(entry.path_str.clone())
+                .or_default()
+                .modified = Some(entry.entry.modified.clone());
+        }
+        let name = entry_name(&entry.path_str).to_string();
+        self.dirs
+            .entry(parent_dir(&entry.path_str).to_string())
+            .or_default()
+            .entries
+            .insert(name, entry);
+    }
+
+    /// Drops the entry at a remote path, e.g. a file that failed to fetch, so
+    /// the next run lists its directory again and processes the entry anew
+    pub fn forget(&mut self, path: &str) {
+        if let Some(listing) = self.dirs.get_mut(parent_dir(path)) {
+            listing.entries.remove(entry_name(path));
+            listing.modified = None;
+        }
+    }
+
+    /// Drops the listing of a directory whose contents are unknown, so the
+    /// next run lists it again
+    pub fn forget_dir(&mut self, dir: &str) {
+        self.dirs.remove(dir);
+    }
+}
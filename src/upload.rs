@@ -0,0 +1,436 @@
+//! Uploads of local files and directories to the Alist server.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use clap::ValueEnum;
+use futures::{Stream, StreamExt, stream};
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use percent_encoding::utf8_percent_encode;
+use reqwest::{
+    Body, StatusCode,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderValue},
+    multipart::{Form, Part},
+};
+use tokio::{fs::File, io::AsyncReadExt};
+use tracing::{debug, error};
+use walkdir::WalkDir;
+
+use crate::api::{
+    AlistClient, auth,
+    operations::PATH_SEGMENT,
+    types::{HashObject, TaskInfo, UploadResponse},
+};
+
+/// Size of the chunks read from a file being uploaded
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Endpoint used to send file contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum UploadMethod {
+    /// `PUT /api/fs/put` with the file as the request body
+    #[default]
+    Stream,
+    /// `PUT /api/fs/form` with the file as a multipart form field
+    Form,
+}
+
+impl UploadMethod {
+    fn endpoint(self) -> &'static str {
+        match self {
+            UploadMethod::Stream => "/api/fs/put",
+            UploadMethod::Form => "/api/fs/form",
+        }
+    }
+}
+
+/// What happens when the remote file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OverwritePolicy {
+    /// Replace it
+    #[default]
+    Overwrite,
+    /// Keep it and skip the local file
+    Skip,
+    /// Replace it only if its size differs from the local file
+    Changed,
+}
+
+/// How files are uploaded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UploadOptions {
+    pub method: UploadMethod,
+    /// Let the server move the file to the storage in a background task
+    /// instead of waiting for it
+    pub as_task: bool,
+    pub overwrite: OverwritePolicy,
+    /// Send the MD5 and SHA1 of each file so storages supporting rapid
+    /// upload can skip the transfer
+    pub rapid: bool,
+}
+
+/// Result of uploading one file
+#[derive(Debug)]
+pub enum UploadOutcome {
+    /// The file was sent, with the server task if it was sent with `As-Task`
+    Uploaded(Option<TaskInfo>),
+    /// The remote file was kept, see [`OverwritePolicy`]
+    Skipped,
+}
+
+/// Outcome of [`AlistClient::upload_path`]
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    /// Remote paths of the uploaded files
+    pub uploaded: Vec<String>,
+    /// Tasks the server started for uploads sent with `As-Task`
+    pub tasks: Vec<TaskInfo>,
+    /// Number of files kept on the server
+    pub skipped: usize,
+    /// Local files that could not be uploaded, with the error
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Joins a remote directory and a relative local path
+fn remote_join(dir: &str, relative: &Path) -> String {
+    let mut path = dir.trim_end_matches('/').to_string();
+    for component in relative.components() {
+        path.push('/');
+        path.push_str(&component.as_os_str().to_string_lossy());
+    }
+    path
+}
+
+/// Lists the files an upload sends, with their remote paths
+///
+/// # Arguments
+///
+/// * `local_path` - A file, or a directory whose contents are uploaded
+/// * `remote_dir` - Remote directory receiving them
+///
+/// # Returns
+///
+/// Pairs of local file and remote path, directories sorted by name
+///
+/// # Errors
+///
+/// Returns an error if `local_path` does not exist or cannot be walked
+pub fn upload_targets(local_path: &Path, remote_dir: &str) -> Result<Vec<(PathBuf, String)>> {
+    if local_path.is_file() {
+        let name = local_path
+            .file_name()
+            .ok_or_else(|| anyhow!("'{}' has no file name", local_path.display()))?;
+        return Ok(vec![(
+            local_path.to_path_buf(),
+            remote_join(remote_dir, Path::new(name)),
+        )]);
+    }
+    if !local_path.is_dir() {
+        return Err(anyhow!("'{}' does not exist", local_path.display()));
+    }
+
+    let mut targets = Vec::new();
+    for entry in WalkDir::new(local_path).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(local_path)?;
+            targets.push((
+                entry.path().to_path_buf(),
+                remote_join(remote_dir, relative),
+            ));
+        }
+    }
+    Ok(targets)
+}
+
+/// Streams a file in chunks, counting the bytes read on the progress bar
+/// and in `sent`
+fn file_stream(
+    file: File,
+    pb: ProgressBar,
+    sent: Arc<AtomicU64>,
+) -> impl Stream<Item = std::io::Result<Bytes>> {
+    stream::try_unfold(file, move |mut file| {
+        let pb = pb.clone();
+        let sent = Arc::clone(&sent);
+        async move {
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buffer.truncate(n);
+            pb.inc(n as u64);
+            sent.fetch_add(n as u64, Ordering::Relaxed);
+            Ok(Some((Bytes::from(buffer), file)))
+        }
+    })
+}
+
+impl AlistClient {
+    /// Uploads a local file, or every file under a local directory
+    ///
+    /// # Arguments
+    ///
+    /// * `local_path` - A file, or a directory whose contents are uploaded
+    /// * `remote_dir` - Remote directory receiving them
+    /// * `options` - Endpoint, overwrite policy and headers to use
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// What was uploaded, skipped or failed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `local_path` cannot be read. Individual file
+    /// failures are logged and collected in the summary.
+    pub async fn upload_path(
+        &self,
+        local_path: &Path,
+        remote_dir: &str,
+        options: UploadOptions,
+        m_pb: MultiProgress,
+    ) -> Result<UploadSummary> {
+        let targets = upload_targets(local_path, remote_dir)?;
+        Ok(self.upload_files(targets, options, m_pb).await)
+    }
+
+    /// Uploads files, running up to `threads` uploads at once
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - Pairs of local file and remote path
+    /// * `options` - Endpoint, overwrite policy and headers to use
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// What was uploaded, skipped or failed; individual file failures are
+    /// logged and collected in the summary
+    pub async fn upload_files(
+        &self,
+        targets: Vec<(PathBuf, String)>,
+        options: UploadOptions,
+        m_pb: MultiProgress,
+    ) -> UploadSummary {
+        let total = targets
+            .iter()
+            .filter_map(|(local, _)| std::fs::metadata(local).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let pb = m_pb.add(ProgressBar::new(total));
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})",
+            )
+            .unwrap()
+            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
+                write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
+            })
+            .progress_chars("#>-"),
+        );
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+        let mut results = stream::iter(targets)
+            .map(|(local, remote)| {
+                let pb = pb.clone();
+                let m_pb = m_pb.clone();
+                async move {
+                    let result = self.upload_file(&local, &remote, options, &pb, m_pb).await;
+                    (local, remote, result)
+                }
+            })
+            .buffer_unordered(self.config().threads.max(1));
+
+        let mut summary = UploadSummary::default();
+        while let Some((local, remote, result)) = results.next().await {
+            match result {
+                Ok(UploadOutcome::Uploaded(task)) => {
+                    debug!("Uploaded {} to {}", local.display(), remote);
+                    summary.uploaded.push(remote);
+                    summary.tasks.extend(task);
+                }
+                Ok(UploadOutcome::Skipped) => summary.skipped += 1,
+                Err(e) => {
+                    error!("Failed to upload {}: {}", local.display(), e);
+                    summary.failed.push((local, e.to_string()));
+                }
+            }
+        }
+        pb.finish_with_message(format!("Uploaded {} files", summary.uploaded.len()));
+        summary
+    }
+
+    /// Uploads one local file
+    ///
+    /// If the server rejects the token and credentials are configured, logs
+    /// in again and sends the file once more.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_path` - The local file
+    /// * `remote_path` - Full remote path to write
+    /// * `options` - Endpoint, overwrite policy and headers to use
+    /// * `pb` - Progress bar counting the bytes sent
+    /// * `m_pb` - Multi-progress bar for hashing feedback
+    ///
+    /// # Returns
+    ///
+    /// Whether the file was sent or kept on the server
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or the server rejects it
+    pub async fn upload_file(
+        &self,
+        local_path: &Path,
+        remote_path: &str,
+        options: UploadOptions,
+        pb: &ProgressBar,
+        m_pb: MultiProgress,
+    ) -> Result<UploadOutcome> {
+        let metadata = tokio::fs::metadata(local_path).await?;
+        let size = metadata.len();
+
+        if options.overwrite != OverwritePolicy::Overwrite &&
+            let Some(remote) = self.remote_file(remote_path).await? &&
+            (options.overwrite == OverwritePolicy::Skip || remote.size == size)
+        {
+            debug!("Keeping existing remote file {}", remote_path);
+            pb.inc(size);
+            return Ok(UploadOutcome::Skipped);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "File-Path",
+            HeaderValue::from_str(&utf8_percent_encode(remote_path, PATH_SEGMENT).to_string())?,
+        );
+        headers.insert(
+            "As-Task",
+            HeaderValue::from_static(if options.as_task { "true" } else { "false" }),
+        );
+        headers.insert("Overwrite", HeaderValue::from_static("true"));
+        // Milliseconds since the epoch, kept by storages that support it
+        if let Some(modified) = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        {
+            headers.insert(
+                "Last-Modified",
+                HeaderValue::from(modified.as_millis() as u64),
+            );
+        }
+        if options.rapid {
+            let md5 = HashObject::Md5 { md5: String::new() };
+            let sha1 = HashObject::Sha1 {
+                sha1: String::new(),
+            };
+            for (name, kind) in [("X-File-Md5", md5), ("X-File-Sha1", sha1)] {
+                let digest = self.file_digest(&kind, local_path, m_pb.clone()).await?;
+                headers.insert(name, HeaderValue::from_str(&digest)?);
+            }
+        }
+
+        let token = self.current_token().await;
+        let (mut status, mut body) = self
+            .send_upload(local_path, size, &headers, options.method, &token, pb)
+            .await?;
+        if self.can_refresh() && auth::is_auth_failure(status, &body) {
+            self.refresh_token(&token).await?;
+            (status, body) = self
+                .send_upload(
+                    local_path,
+                    size,
+                    &headers,
+                    options.method,
+                    &self.current_token().await,
+                    pb,
+                )
+                .await?;
+        }
+
+        if !status.is_success() {
+            return Err(anyhow!("HTTP error: {}", status));
+        }
+        let response: UploadResponse = serde_json::from_slice(&body)
+            .map_err(|e| anyhow!("Failed to parse upload response: {}", e))?;
+        if response.code != 200 {
+            return Err(anyhow!(
+                "Upload rejected with code {}: {}",
+                response.code,
+                response.message
+            ));
+        }
+        Ok(UploadOutcome::Uploaded(
+            response.data.and_then(|data| data.task),
+        ))
+    }
+
+    /// Sends a file to an upload endpoint and buffers the response
+    ///
+    /// The bytes sent are taken off the progress bar again unless the server
+    /// accepted the request.
+    async fn send_upload(
+        &self,
+        local_path: &Path,
+        size: u64,
+        headers: &HeaderMap,
+        method: UploadMethod,
+        token: &str,
+        pb: &ProgressBar,
+    ) -> Result<(StatusCode, Bytes)> {
+        // Wait until we're allowed to make a request
+        self.wait_for_permit().await?;
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let file = File::open(local_path).await?;
+        let body = Body::wrap_stream(file_stream(file, pb.clone(), Arc::clone(&sent)));
+        let request = self
+            .http()
+            .put(self.api_url(method.endpoint()))
+            .header("Authorization", token)
+            .headers(headers.clone());
+        let request = match method {
+            UploadMethod::Stream => request
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header(CONTENT_LENGTH, size)
+                .body(body),
+            UploadMethod::Form => {
+                let name = local_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let part = Part::stream_with_length(body, size)
+                    .file_name(name)
+                    .mime_str("application/octet-stream")?;
+                request.multipart(Form::new().part("file", part))
+            }
+        };
+
+        let result = async {
+            let response = request.send().await?;
+            let status = response.status();
+            Ok::<_, anyhow::Error>((status, response.bytes().await?))
+        }
+        .await;
+        let accepted = matches!(&result, Ok((status, body))
+            if status.is_success() && !auth::is_auth_failure(*status, body));
+        if !accepted {
+            pb.dec(sent.load(Ordering::Relaxed));
+        }
+        result
+    }
+}
@@ -1,11 +1,14 @@
 //! File operations and download utilities.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, anyhow};
 use indicatif::MultiProgress;
-use reqwest::Client;
-use tokio::fs;
+use reqwest::{
+    Response, StatusCode,
+    header::{CONTENT_RANGE, ETAG, HeaderMap, HeaderValue, IF_RANGE, LAST_MODIFIED, RANGE},
+};
+use tokio::{fs, io::AsyncWriteExt};
 use tracing::{debug, info};
 
 /// Ensures the parent directory of a file path exists, creating it if
@@ -31,74 +34,146 @@ pub async fn ensure_parent_dir(path: &Path) -> Result<()> {
     Ok(())
 }
 
-use crate::api::{
-    rate_limiter::rate_limited_get,
-    types::{EntryWithPath, HashObject},
-};
+/// Replaces a file atomically, creating its parent directory if needed
+///
+/// The contents go to a `.tmp` sibling that is renamed over `path`, so
+/// readers never see a partly written file.
+///
+/// # Arguments
+///
+/// * `path` - The file to write
+/// * `contents` - The new contents
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomic_with_mode(path, contents, None).await
+}
 
-/// Maximum number of retry attempts for downloads
-const MAX_RETRIES: u32 = 3;
+/// Like [`write_atomic`], but only the owner may read the new file
+///
+/// The temporary file is created with mode `0o600` on Unix, so the contents
+/// are never readable by others, not even briefly.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written
+pub async fn write_atomic_private(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomic_with_mode(path, contents, Some(0o600)).await
+}
 
-/// Initial delay for exponential backoff in milliseconds
-const INITIAL_BACKOFF_MS: u64 = 500;
+/// Writes through a `.tmp` sibling, created with Unix `mode` if given
+async fn write_atomic_with_mode(path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+    ensure_parent_dir(path).await?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    // A leftover temporary file may have other permissions
+    let _ = fs::remove_file(&tmp_path).await;
 
-/// Maximum backoff delay in milliseconds
-const MAX_BACKOFF_MS: u64 = 10000;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options.open(&tmp_path).await?;
+    file.write_all(contents).await?;
+    file.flush().await?;
+    drop(file);
+
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
 
-/// Downloads a file with retry logic and optional checksum verification.
+/// Writes a file only if its contents differ, see [`write_atomic`]
 ///
 /// # Arguments
 ///
-/// * `raw_url` - The URL to download from
-/// * `local_path` - Local path where the file should be saved
-/// * `client` - HTTP client for making requests
-/// * `checksum` - Optional hash for verification
-/// * `m_pb` - Multi-progress bar for UI feedback
+/// * `path` - The file to write
+/// * `contents` - The new contents
 ///
 /// # Returns
 ///
-/// Success if the file was downloaded and verified
+/// `true` if the file was written, `false` if it already had these contents
 ///
 /// # Errors
 ///
-/// Returns an error if all retry attempts fail
-pub async fn download_file_with_retries(
-    raw_url: &str,
-    local_path: &Path,
-    client: &Client,
-    checksum: Option<HashObject>,
-    m_pb: MultiProgress,
-) -> Result<()> {
-    for attempt in 1..=MAX_RETRIES {
-        match attempt_download_file(raw_url, local_path, client, checksum.clone(), m_pb.clone())
-            .await
-        {
-            Ok(_) => return Ok(()),
-            Err(e) if attempt < MAX_RETRIES => {
-                // Calculate exponential backoff delay
-                let backoff_ms =
-                    std::cmp::min(INITIAL_BACKOFF_MS * 2_u64.pow(attempt - 1), MAX_BACKOFF_MS);
-                info!(
-                    "Download attempt #{} for '{}' failed: {}. Retrying in {}ms...",
-                    attempt, raw_url, e, backoff_ms
-                );
-                tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
-            }
-
-            Err(e) => {
-                return Err(anyhow!(
-                    "Failed after {} attempts for '{}': {}",
-                    attempt,
-                    raw_url,
-                    e
-                ));
-            }
-        }
+/// Returns an error if the file cannot be written
+pub async fn write_if_changed(path: &Path, contents: &[u8]) -> Result<bool> {
+    if fs::read(path)
+        .await
+        .is_ok_and(|existing| existing == contents)
+    {
+        return Ok(false);
     }
-    // Should never reach here unless the loop is changed.
-    unreachable!("All retry attempts have returned by this point.");
+    write_atomic(path, contents).await?;
+    Ok(true)
+}
+
+/// Sets the modification time of a file to a remote `modified` timestamp
+///
+/// # Arguments
+///
+/// * `path` - The local file
+/// * `modified` - RFC 3339 timestamp from the listing
+///
+/// # Errors
+///
+/// Returns an error if the timestamp is invalid or the file cannot be updated
+pub async fn set_modified(path: &Path, modified: &str) -> Result<()> {
+    let modified = chrono::DateTime::parse_from_rfc3339(modified)
+        .map_err(|e| anyhow!("Invalid timestamp '{}': {}", modified, e))?;
+    let file = fs::OpenOptions::new().write(true).open(path).await?;
+    file.into_std().await.set_modified(modified.into())?;
+    Ok(())
+}
+
+/// Checks if a local file already mirrors a remote one, by its size and its
+/// modification time as set by [`set_modified`]
+///
+/// Timestamps are compared to the second, as some filesystems store no
+/// finer resolution.
+///
+/// # Arguments
+///
+/// * `local_path` - The local copy
+/// * `entry` - The remote file from the listing
+///
+/// # Returns
+///
+/// `false` if the file is missing, differs in size or time, or the remote
+/// timestamp cannot be parsed
+pub async fn is_local_copy_current(local_path: &Path, entry: &EntryInfo) -> bool {
+    let Ok(metadata) = fs::metadata(local_path).await else {
+        return false;
+    };
+    let Ok(remote) = chrono::DateTime::parse_from_rfc3339(&entry.modified) else {
+        return false;
+    };
+    metadata.is_file() &&
+        metadata.len() == entry.size &&
+        metadata.modified().is_ok_and(|local| {
+            chrono::DateTime::<chrono::Utc>::from(local).timestamp() == remote.timestamp()
+        })
 }
 
+use crate::api::{
+    AlistClient,
+    types::{EntryInfo, EntryWithPath, HashObject},
+};
+
+/// Maximum number of retry attempts for downloads
+const MAX_RETRIES: u32 = 3;
+
+/// Initial delay for exponential backoff in milliseconds
+const INITIAL_BACKOFF_MS: u64 = 500;
+
+/// Maximum backoff delay in milliseconds
+const MAX_BACKOFF_MS: u64 = 10000;
+
 /// Checks if the provider supports reliable checksums.
 ///
 /// # Arguments
@@ -115,84 +190,340 @@ pub fn provider_checksum(entry: &EntryWithPath) -> bool {
     true
 }
 
-/// Attempts to download a file once with checksum verification.
+/// Returns the path of the partial download sidecar for a file
 ///
 /// # Arguments
 ///
-/// * `raw_url` - The URL to download from
-/// * `local_path` - Local path where the file should be saved
-/// * `client` - HTTP client for making requests
-/// * `checksum` - Optional hash for verification
-/// * `m_pb` - Multi-progress bar for UI feedback
+/// * `local_path` - Final path of the downloaded file
 ///
 /// # Returns
 ///
-/// Success if the file was downloaded and verified
+/// `local_path` with `.part` appended
+pub fn part_path(local_path: &Path) -> PathBuf {
+    let mut path = local_path.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+/// Returns the path storing the `ETag`/`Last-Modified` of a partial download
+pub(crate) fn validator_path(local_path: &Path) -> PathBuf {
+    let mut path = part_path(local_path).into_os_string();
+    path.push(".etag");
+    PathBuf::from(path)
+}
+
+/// Parses the first byte position from a `Content-Range` header
+///
+/// # Arguments
+///
+/// * `content_range` - Header value such as `bytes 100-999/1000`
 ///
-/// # Errors
+/// # Returns
 ///
-/// Returns an error if the download or verification fails
-async fn attempt_download_file(
-    raw_url: &str,
-    local_path: &Path,
-    client: &Client,
-    checksum: Option<HashObject>,
-    m_pb: MultiProgress,
-) -> Result<()> {
-    debug!("Download to local file path: {}", local_path.display());
-
-    if let Some(checksum_obj) = &checksum &&
-        checksum_obj
-            .verify_file_checksum(local_path, m_pb.clone())
+/// The start offset, or `None` if the header is malformed
+pub fn parse_content_range_start(content_range: &str) -> Option<u64> {
+    content_range
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Returns the validator the server gave for this response, preferring the
+/// `ETag`
+fn response_validator(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .or_else(|| response.headers().get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+impl AlistClient {
+    /// Downloads a file with retry logic and optional checksum verification.
+    ///
+    /// Files of at least `segment_threshold` bytes are fetched as `segments`
+    /// concurrent ranges when the server supports it.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_url` - The URL to download from
+    /// * `local_path` - Local path where the file should be saved
+    /// * `checksum` - Optional hash for verification
+    /// * `size` - Expected file size, `0` if the provider does not report it
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// Success if the file was downloaded and verified
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if all retry attempts fail
+    pub async fn download_file_with_retries(
+        &self,
+        raw_url: &str,
+        local_path: &Path,
+        checksum: Option<HashObject>,
+        size: u64,
+        m_pb: MultiProgress,
+    ) -> Result<()> {
+        let config = self.config();
+        if config.segments > 1 &&
+            size > 0 &&
+            size >= config.segment_threshold &&
+            self.download_segmented(
+                raw_url,
+                local_path,
+                checksum.as_ref(),
+                size,
+                config.segments,
+                m_pb.clone(),
+            )
             .await?
-    {
-        return Ok(());
-    }
+        {
+            return Ok(());
+        }
 
-    // Send GET Request
-    let mut response = rate_limited_get(client, raw_url)
-        .await
-        .map_err(|e| anyhow!("Request failed for '{}': {}", raw_url, e))?;
-
-    // Check status code
-    if !response.status().is_success() {
-        return Err(anyhow!(
-            "Server returned error status {} for '{}'",
-            response.status(),
-            raw_url
-        ));
+        for attempt in 1..=MAX_RETRIES {
+            match self
+                .attempt_download_file(raw_url, local_path, checksum.clone(), size, m_pb.clone())
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt < MAX_RETRIES => {
+                    // Calculate exponential backoff delay
+                    let backoff_ms =
+                        std::cmp::min(INITIAL_BACKOFF_MS * 2_u64.pow(attempt - 1), MAX_BACKOFF_MS);
+                    info!(
+                        "Download attempt #{} for '{}' failed: {}. Retrying in {}ms...",
+                        attempt, raw_url, e, backoff_ms
+                    );
+                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+                }
+
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Failed after {} attempts for '{}': {}",
+                        attempt,
+                        raw_url,
+                        e
+                    ));
+                }
+            }
+        }
+        // Should never reach here unless the loop is changed.
+        unreachable!("All retry attempts have returned by this point.");
     }
 
-    // Ensure the parent directory exists
-    ensure_parent_dir(local_path).await?;
+    /// Attempts to download a file once with checksum verification.
+    ///
+    /// Data is written to a `.part` sidecar next to `local_path`. If a sidecar
+    /// is left over from an earlier attempt, the download resumes with a
+    /// `Range` request guarded by the stored `ETag`, see
+    /// [`AlistClient::fetch_part`]. A sidecar with neither a stored validator
+    /// nor a checksum to verify it is discarded, and a complete one is
+    /// verified without a request. The sidecar is renamed into place only
+    /// after size and checksum verification pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_url` - The URL to download from
+    /// * `local_path` - Local path where the file should be saved
+    /// * `checksum` - Optional hash for verification
+    /// * `size` - Expected file size, `0` if the provider does not report it
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// Success if the file was downloaded and verified
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the download or verification fails
+    async fn attempt_download_file(
+        &self,
+        raw_url: &str,
+        local_path: &Path,
+        checksum: Option<HashObject>,
+        size: u64,
+        m_pb: MultiProgress,
+    ) -> Result<()> {
+        debug!("Download to local file path: {}", local_path.display());
 
-    // Create or truncate the file
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&local_path)
-        .await
-        .map_err(|e| anyhow!("Failed to open file '{:?}': {}", local_path, e))?;
+        if let Some(checksum_obj) = &checksum &&
+            self.verify_checksum(checksum_obj, local_path, m_pb.clone())
+                .await?
+        {
+            return Ok(());
+        }
 
-    // Stream the file contents
-    use tokio::io::AsyncWriteExt;
-    while let Some(chunk) = response.chunk().await? {
-        file.write_all(&chunk).await?
-    }
+        // Ensure the parent directory exists
+        ensure_parent_dir(local_path).await?;
+
+        let part_path = part_path(local_path);
+        let validator_path = validator_path(local_path);
+
+        // Resume from a leftover sidecar unless it is larger than the file, or
+        // neither a validator nor a checksum would catch a changed file
+        let validator = fs::read_to_string(&validator_path)
+            .await
+            .ok()
+            .and_then(|validator| HeaderValue::from_str(validator.trim()).ok());
+        let mut offset = fs::metadata(&part_path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if (size > 0 && offset > size) || (validator.is_none() && checksum.is_none()) {
+            offset = 0;
+        }
+
+        if size > 0 && offset == size {
+            debug!("Sidecar of '{}' is complete, verifying it", raw_url);
+        } else {
+            self.fetch_part(raw_url, &part_path, &validator_path, offset, validator)
+                .await?;
+        }
 
-    // Verify the file checksum (if provided)
-    if let Some(checksum_obj) = &checksum {
-        let verified = checksum_obj
-            .verify_file_checksum(local_path, m_pb.clone())
-            .await?;
-        if !verified {
+        // Check the size (if known) before spending time on the checksum
+        let written = fs::metadata(&part_path).await?.len();
+        if size > 0 && written != size {
+            if written > size {
+                let _ = fs::remove_file(&part_path).await;
+            }
             return Err(anyhow!(
-                "Checksum mismatch. Downloaded file does not match the expected hash."
+                "Size mismatch. Expected {} bytes, got {}",
+                size,
+                written
             ));
         }
-        debug!("Downloaded file verified successfully against the provided hash.");
+
+        // Verify the file checksum (if provided)
+        if let Some(checksum_obj) = &checksum {
+            let verified = self
+                .verify_checksum(checksum_obj, &part_path, m_pb.clone())
+                .await?;
+            if !verified {
+                // The sidecar is corrupt, the next attempt must start over
+                let _ = fs::remove_file(&part_path).await;
+                return Err(anyhow!(
+                    "Checksum mismatch. Downloaded file does not match the expected hash."
+                ));
+            }
+            debug!("Downloaded file verified successfully against the provided hash.");
+        }
+
+        fs::rename(&part_path, local_path).await?;
+        self.hash_cache().rename(&part_path, local_path);
+        let _ = fs::remove_file(&validator_path).await;
+
+        Ok(())
     }
 
-    Ok(())
+    /// Streams a file into its `.part` sidecar, resuming at `offset`.
+    ///
+    /// A resumed request is guarded by the stored validator; servers that do
+    /// not honor it send the whole file and the sidecar is rewritten from the
+    /// start, as it is when the server rejects the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_url` - The URL to download from
+    /// * `part_path` - The sidecar
+    /// * `validator_path` - File storing the validator of the sidecar
+    /// * `offset` - Length of the sidecar to keep, `0` to start over
+    /// * `validator` - `ETag` or `Last-Modified` the sidecar was written with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the sidecar cannot be written
+    async fn fetch_part(
+        &self,
+        raw_url: &str,
+        part_path: &Path,
+        validator_path: &Path,
+        mut offset: u64,
+        validator: Option<HeaderValue>,
+    ) -> Result<()> {
+        let mut response = loop {
+            let mut headers = HeaderMap::new();
+            if offset > 0 {
+                headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={offset}-"))?);
+                if let Some(validator) = &validator {
+                    headers.insert(IF_RANGE, validator.clone());
+                }
+            }
+
+            // Send GET Request
+            let response = self
+                .rate_limited_get(raw_url, headers)
+                .await
+                .map_err(|e| anyhow!("Request failed for '{}': {}", raw_url, e))?;
+
+            // The sidecar is not a prefix of the current file, e.g. the file shrank
+            if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                debug!(
+                    "Server rejected resuming '{}' at byte {}, restarting",
+                    raw_url, offset
+                );
+                let _ = fs::remove_file(part_path).await;
+                let _ = fs::remove_file(validator_path).await;
+                offset = 0;
+                continue;
+            }
+            break response;
+        };
+
+        // Check status code
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Server returned error status {} for '{}'",
+                response.status(),
+                raw_url
+            ));
+        }
+
+        let resumed = offset > 0 &&
+            response.status() == StatusCode::PARTIAL_CONTENT &&
+            response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range_start) ==
+                Some(offset);
+        if offset > 0 && !resumed {
+            debug!(
+                "Server did not resume '{}' at byte {}, restarting",
+                raw_url, offset
+            );
+        }
+
+        // Remember the validator so a later attempt can resume safely
+        match response_validator(&response) {
+            Some(validator) => fs::write(validator_path, validator).await?,
+            None => {
+                let _ = fs::remove_file(validator_path).await;
+            }
+        }
+
+        // Append to the sidecar when resuming, otherwise create or truncate it
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(part_path)
+            .await
+            .map_err(|e| anyhow!("Failed to open file '{:?}': {}", part_path, e))?;
+
+        // Stream the file contents
+        while let Some(chunk) = self.read_chunk(&mut response).await? {
+            file.write_all(&chunk).await?
+        }
+        file.flush().await?;
+        Ok(())
+    }
 }
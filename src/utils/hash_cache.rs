@@ -0,0 +1,306 @@
+//! Persistent cache of local file digests.
+//!
+//! Hashing a large mirror to find out that nothing changed reads every byte
+//! of it. The cache remembers the SHA1/MD5 digests computed for each local
+//! file together with its size, modification time and inode, and a digest is
+//! only reused while all three are unchanged.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use indicatif::MultiProgress;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, warn};
+
+use super::file_ops::write_atomic;
+use crate::api::{AlistClient, types::HashObject};
+
+/// Identity of one version of a local file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    size: u64,
+    /// Modification time since the Unix epoch
+    modified: Duration,
+    /// Inode number, `0` where the platform has none
+    inode: u64,
+}
+
+impl FileStamp {
+    fn of(metadata: &std::fs::Metadata) -> Option<Self> {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?,
+            inode,
+        })
+    }
+}
+
+/// Digests computed for one version of a file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedDigests {
+    stamp: FileStamp,
+    sha1: Option<String>,
+    md5: Option<String>,
+}
+
+impl CachedDigests {
+    fn new(stamp: FileStamp) -> Self {
+        Self {
+            stamp,
+            sha1: None,
+            md5: None,
+        }
+    }
+
+    fn digest(&self, kind: &HashObject) -> Option<&String> {
+        match kind {
+            HashObject::Sha1 { .. } => self.sha1.as_ref(),
+            HashObject::Md5 { .. } => self.md5.as_ref(),
+        }
+    }
+
+    fn slot(&mut self, kind: &HashObject) -> &mut Option<String> {
+        match kind {
+            HashObject::Sha1 { .. } => &mut self.sha1,
+            HashObject::Md5 { .. } => &mut self.md5,
+        }
+    }
+}
+
+/// Digests of local files by path
+#[derive(Debug, Default)]
+pub struct HashCache {
+    /// File the cache is persisted to, `None` to keep it in memory
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, CachedDigests>>,
+    /// Set when entries changed since loading
+    dirty: AtomicBool,
+}
+
+/// Returns the cache key of a local path
+fn key(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl HashCache {
+    /// Loads the cache, starting empty if the file is missing or unreadable
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Cache file, or `None` for a cache that is never persisted
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path
+            .as_deref()
+            .and_then(|path| match std::fs::read(path) {
+                Ok(content) => serde_json::from_slice(&content)
+                    .inspect_err(|e| {
+                        warn!("Ignoring invalid hash cache '{}': {}", path.display(), e)
+                    })
+                    .ok(),
+                Err(_) => None,
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Writes the cache if it changed, replacing the file atomically
+    ///
+    /// Entries of files that were deleted or changed since they were hashed
+    /// are dropped first, so the cache does not grow with every file that
+    /// ever passed through the mirror.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub async fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let evicted = self.evict_stale();
+        if !self.dirty.swap(false, Ordering::AcqRel) && evicted == 0 {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&*self.entries.lock().unwrap())?;
+        write_atomic(path, &content).await
+    }
+
+    /// Drops the entries that can never be used again
+    ///
+    /// # Returns
+    ///
+    /// The number of entries dropped
+    fn evict_stale(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|path, cached| {
+            std::fs::metadata(path)
+                .ok()
+                .and_then(|metadata| FileStamp::of(&metadata)) ==
+                Some(cached.stamp)
+        });
+        let evicted = before - entries.len();
+        if evicted > 0 {
+            debug!("Dropped {} stale hash cache entries", evicted);
+        }
+        evicted
+    }
+
+    /// Returns the cached digest of a file if the file is unchanged
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The local file
+    /// * `metadata` - Its current metadata
+    /// * `kind` - Which digest to look up
+    pub fn get(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        kind: &HashObject,
+    ) -> Option<String> {
+        let stamp = FileStamp::of(metadata)?;
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&key(path))
+            .filter(|cached| cached.stamp == stamp)?
+            .digest(kind)
+            .cloned()
+    }
+
+    /// Records the digest of a file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The local file
+    /// * `metadata` - Its metadata from before it was hashed
+    /// * `kind` - Which digest was computed
+    /// * `digest` - The digest
+    pub fn insert(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        kind: &HashObject,
+        digest: String,
+    ) {
+        let Some(stamp) = FileStamp::of(metadata) else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let cached = entries
+            .entry(key(path))
+            .or_insert_with(|| CachedDigests::new(stamp));
+        // Digests of an older version of the file are stale
+        if cached.stamp != stamp {
+            *cached = CachedDigests::new(stamp);
+        }
+        *cached.slot(kind) = Some(digest);
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Moves the digests of a file that was renamed
+    pub fn rename(&self, from: &Path, to: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(cached) = entries.remove(&key(from)) {
+            entries.insert(key(to), cached);
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl AlistClient {
+    /// Computes a digest of a local file, reusing the digest cached for an
+    /// unchanged file instead of reading it again.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Which digest to compute; the hash it holds is ignored
+    /// * `local_path` - Path to the file to hash
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// The digest as a lowercase hexadecimal string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read
+    pub async fn file_digest(
+        &self,
+        kind: &HashObject,
+        local_path: &Path,
+        m_pb: MultiProgress,
+    ) -> Result<String> {
+        let metadata = fs::metadata(local_path).await?;
+        let cache = self.hash_cache();
+        if let Some(digest) = cache.get(local_path, &metadata, kind) {
+            debug!("Using cached checksum of {}", local_path.display());
+            return Ok(digest);
+        }
+        let digest = kind.compute_file_checksum(local_path, m_pb).await?;
+        cache.insert(local_path, &metadata, kind, digest.clone());
+        Ok(digest)
+    }
+
+    /// Verifies that a local file matches the expected checksum, see
+    /// [`AlistClient::file_digest`]
+    ///
+    /// # Arguments
+    ///
+    /// * `checksum` - The expected hash
+    /// * `local_path` - Path to the file to verify
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// `true` if the file exists and matches the expected hash
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read
+    pub async fn verify_checksum(
+        &self,
+        checksum: &HashObject,
+        local_path: &Path,
+        m_pb: MultiProgress,
+    ) -> Result<bool> {
+        if fs::metadata(local_path).await.is_err() {
+            return Ok(false);
+        }
+        let computed = self.file_digest(checksum, local_path, m_pb).await?;
+        debug!(
+            "local checksum: {} remote file checksum: {}",
+            computed,
+            checksum.as_hash_str()
+        );
+        Ok(computed == checksum.as_hash_str())
+    }
+
+    /// Persists the digest cache if it changed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache file cannot be written
+    pub async fn save_hash_cache(&self) -> Result<()> {
+        self.hash_cache().save().await
+    }
+}
@@ -0,0 +1,63 @@
+//! Lock file preventing overlapping runs on the same local path.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+
+/// Lock file name, created inside the local path
+pub const LOCK_FILE: &str = ".alist_cli.lock";
+
+/// Exclusive lock on a local path, released when dropped
+pub struct RunLock {
+    _file: File,
+}
+
+impl RunLock {
+    /// Returns the lock file location for a local path
+    pub fn path_for(local_path: &Path) -> PathBuf {
+        local_path.join(LOCK_FILE)
+    }
+
+    /// Tries to lock a local path without waiting.
+    ///
+    /// The lock is held through the operating system, so it is released even
+    /// if the process dies.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_path` - The local path to lock, created if missing
+    ///
+    /// # Returns
+    ///
+    /// The lock, or `None` if another process holds it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file cannot be created or locked
+    pub fn try_acquire(local_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(local_path);
+        std::fs::create_dir_all(local_path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to open lock file '{}': {}", path.display(), e))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(anyhow!("Failed to lock '{}': {}", path.display(), e));
+            }
+        }
+
+        // Record the owner to help diagnose a stuck lock
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Some(Self { _file: file }))
+    }
+}
@@ -5,5 +5,10 @@
 
 pub mod crypto;
 pub mod file_ops;
+pub mod hash_cache;
+pub mod lock;
+pub mod rename_journal;
+pub mod segmented;
+pub mod trash;
 
 pub use file_ops::*;
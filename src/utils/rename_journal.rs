@@ -0,0 +1,129 @@
+//! Local journal of batch and regex renames made on the server.
+//!
+//! Every committed rename is recorded with the directory and the old and new
+//! names, so the most recent one can be reverted with a batch rename in the
+//! opposite direction.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::file_ops::write_atomic;
+use crate::api::types::RenameObject;
+
+/// Number of renames kept in the journal, older ones are dropped
+pub const MAX_BATCHES: usize = 50;
+
+/// Renames committed together in one directory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RenameBatch {
+    /// When the renames were made, RFC 3339
+    pub time: String,
+    /// Address of the Alist server
+    pub server: String,
+    /// Remote directory holding the entries
+    pub dir: String,
+    pub renames: Vec<RenameObject>,
+}
+
+impl RenameBatch {
+    /// Creates a batch of renames made now
+    pub fn new(server: &str, dir: &str, renames: Vec<RenameObject>) -> Self {
+        Self {
+            time: Local::now().to_rfc3339(),
+            server: server.to_string(),
+            dir: dir.to_string(),
+            renames,
+        }
+    }
+
+    /// Returns the renames reverting this batch
+    pub fn reverted(&self) -> Vec<RenameObject> {
+        self.renames
+            .iter()
+            .map(|rename| RenameObject {
+                src_name: rename.new_name.clone(),
+                new_name: rename.src_name.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Journal file of recent rename batches, oldest first
+#[derive(Debug, Clone)]
+pub struct RenameJournal {
+    path: PathBuf,
+}
+
+impl RenameJournal {
+    /// Creates a journal handle for a file, which is created on first use
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the recorded batches, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal exists but cannot be read or parsed
+    pub async fn batches(&self) -> Result<Vec<RenameBatch>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read(&self.path).await?;
+        serde_json::from_slice(&content)
+            .map_err(|e| anyhow!("Invalid rename journal '{}': {}", self.path.display(), e))
+    }
+
+    /// Returns the most recent batch made on a server
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal cannot be read
+    pub async fn last(&self, server: &str) -> Result<Option<RenameBatch>> {
+        Ok(self
+            .batches()
+            .await?
+            .into_iter()
+            .rev()
+            .find(|batch| batch.server == server))
+    }
+
+    /// Appends a batch, dropping the oldest ones beyond [`MAX_BATCHES`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal cannot be read or written
+    pub async fn record(&self, batch: RenameBatch) -> Result<()> {
+        let mut batches = self.batches().await?;
+        batches.push(batch);
+        let excess = batches.len().saturating_sub(MAX_BATCHES);
+        batches.drain(..excess);
+        self.write(&batches).await
+    }
+
+    /// Removes a batch once it has been reverted
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal cannot be read or written
+    pub async fn forget(&self, batch: &RenameBatch) -> Result<()> {
+        let mut batches = self.batches().await?;
+        batches.retain(|recorded| recorded != batch);
+        self.write(&batches).await
+    }
+
+    /// Replaces the journal atomically
+    async fn write(&self, batches: &[RenameBatch]) -> Result<()> {
+        let content = serde_json::to_vec_pretty(batches)?;
+        write_atomic(&self.path, &content).await
+    }
+}
@@ -0,0 +1,277 @@
+//! Segmented downloads over several concurrent HTTP Range requests.
+
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use futures::future::try_join_all;
+use indicatif::MultiProgress;
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_RANGE, ETAG, HeaderMap, HeaderValue, IF_RANGE, RANGE},
+};
+use tokio::{
+    fs,
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::{debug, info};
+
+use super::file_ops::{self, ensure_parent_dir, parse_content_range_start, validator_path};
+use crate::api::{AlistClient, types::HashObject};
+
+/// Maximum number of retry attempts for a single segment
+const MAX_RETRIES: u32 = 3;
+
+/// Initial delay for exponential backoff in milliseconds
+const INITIAL_BACKOFF_MS: u64 = 500;
+
+/// Maximum backoff delay in milliseconds
+const MAX_BACKOFF_MS: u64 = 10000;
+
+/// Splits a file into contiguous byte ranges.
+///
+/// # Arguments
+///
+/// * `size` - Total file size in bytes
+/// * `segments` - Number of ranges to split into
+///
+/// # Returns
+///
+/// Inclusive `(start, end)` byte ranges covering the whole file, at most
+/// `segments` of them
+pub fn split_ranges(size: u64, segments: usize) -> Vec<(u64, u64)> {
+    if size == 0 || segments == 0 {
+        return Vec::new();
+    }
+    let segment_size = size.div_ceil(segments as u64);
+    (0..size)
+        .step_by(segment_size as usize)
+        .map(|start| (start, std::cmp::min(start + segment_size, size) - 1))
+        .collect()
+}
+
+/// Returns the sidecar a segmented download is preallocated and written in
+///
+/// It is kept apart from the `.part` of single-stream downloads, which would
+/// otherwise find a full-size file of holes to resume from.
+///
+/// # Arguments
+///
+/// * `local_path` - Final path of the downloaded file
+///
+/// # Returns
+///
+/// `local_path` with `.segpart` appended
+pub fn segment_part_path(local_path: &Path) -> PathBuf {
+    let mut path = local_path.as_os_str().to_owned();
+    path.push(".segpart");
+    PathBuf::from(path)
+}
+
+/// Builds the headers requesting `start..=end`, guarded by the validator
+fn range_headers(start: u64, end: u64, validator: Option<&HeaderValue>) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        RANGE,
+        HeaderValue::from_str(&format!("bytes={start}-{end}"))?,
+    );
+    if let Some(validator) = validator {
+        headers.insert(IF_RANGE, validator.clone());
+    }
+    Ok(headers)
+}
+
+impl AlistClient {
+    /// Checks that the server honors `Range` for this URL.
+    ///
+    /// # Returns
+    ///
+    /// The `ETag` to guard segment requests with, or `None` if ranges are not
+    /// supported
+    async fn probe_range_support(&self, raw_url: &str) -> Result<Option<Option<HeaderValue>>> {
+        let response = self
+            .rate_limited_get(raw_url, range_headers(0, 0, None)?)
+            .await?;
+        let supported = response.status() == StatusCode::PARTIAL_CONTENT &&
+            response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range_start) ==
+                Some(0);
+        Ok(supported.then(|| response.headers().get(ETAG).cloned()))
+    }
+
+    /// Downloads `start..=end` into the partial file, resuming within the
+    /// segment on retry.
+    async fn download_segment(
+        &self,
+        raw_url: &str,
+        part_path: &Path,
+        (start, end): (u64, u64),
+        validator: Option<&HeaderValue>,
+    ) -> Result<()> {
+        let mut position = start;
+
+        for attempt in 1..=MAX_RETRIES {
+            let result: Result<()> = async {
+                let headers = range_headers(position, end, validator)?;
+                let mut response = self.rate_limited_get(raw_url, headers).await?;
+                if response.status() != StatusCode::PARTIAL_CONTENT {
+                    return Err(anyhow!(
+                        "Expected partial content, server returned {}",
+                        response.status()
+                    ));
+                }
+
+                let mut file = fs::OpenOptions::new().write(true).open(part_path).await?;
+                file.seek(SeekFrom::Start(position)).await?;
+                while let Some(chunk) = self.read_chunk(&mut response).await? {
+                    let remaining = (end + 1 - position) as usize;
+                    let chunk = &chunk[..std::cmp::min(chunk.len(), remaining)];
+                    file.write_all(chunk).await?;
+                    position += chunk.len() as u64;
+                    if position > end {
+                        break;
+                    }
+                }
+                file.flush().await?;
+
+                if position <= end {
+                    return Err(anyhow!(
+                        "Connection closed at byte {} of segment {}-{}",
+                        position,
+                        start,
+                        end
+                    ));
+                }
+                Ok(())
+            }
+            .await;
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < MAX_RETRIES => {
+                    let backoff_ms =
+                        std::cmp::min(INITIAL_BACKOFF_MS * 2_u64.pow(attempt - 1), MAX_BACKOFF_MS);
+                    debug!(
+                        "Segment {}-{} attempt #{} failed: {}. Retrying in {}ms...",
+                        start, end, attempt, e, backoff_ms
+                    );
+                    tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Segment {}-{} failed after {} attempts: {}",
+                        start,
+                        end,
+                        attempt,
+                        e
+                    ));
+                }
+            }
+        }
+        unreachable!("All retry attempts have returned by this point.");
+    }
+
+    /// Downloads a file as several byte ranges fetched concurrently.
+    ///
+    /// The ranges are written in place into a preallocated `.segpart` sidecar,
+    /// see [`segment_part_path`], which is renamed into place once the
+    /// checksum (if any) has been verified.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_url` - The URL to download from
+    /// * `local_path` - Local path where the file should be saved
+    /// * `checksum` - Optional hash for verification
+    /// * `size` - File size in bytes
+    /// * `segments` - Number of concurrent range requests
+    /// * `m_pb` - Multi-progress bar for UI feedback
+    ///
+    /// # Returns
+    ///
+    /// `false` if the server does not honor `Range`, or probing it failed, and
+    /// the caller should fall back to a single stream, `true` once the file is
+    /// downloaded
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a segment fails after all retries or verification
+    /// fails
+    pub async fn download_segmented(
+        &self,
+        raw_url: &str,
+        local_path: &Path,
+        checksum: Option<&HashObject>,
+        size: u64,
+        segments: usize,
+        m_pb: MultiProgress,
+    ) -> Result<bool> {
+        if let Some(checksum_obj) = checksum &&
+            self.verify_checksum(checksum_obj, local_path, m_pb.clone())
+                .await?
+        {
+            return Ok(true);
+        }
+
+        let validator = match self.probe_range_support(raw_url).await {
+            Ok(Some(validator)) => validator,
+            Ok(None) => {
+                info!(
+                    "Server does not support ranges for '{}', using a single stream",
+                    raw_url
+                );
+                return Ok(false);
+            }
+            // The single stream retries on its own
+            Err(e) => {
+                info!(
+                    "Probing ranges for '{}' failed, using a single stream: {}",
+                    raw_url, e
+                );
+                return Ok(false);
+            }
+        };
+
+        ensure_parent_dir(local_path).await?;
+        let part_path = segment_part_path(local_path);
+        let file = fs::File::create(&part_path).await?;
+        file.set_len(size).await?;
+        drop(file);
+
+        let ranges = split_ranges(size, segments);
+        debug!("Downloading '{}' in {} segments", raw_url, ranges.len());
+        let result =
+            try_join_all(ranges.into_iter().map(|range| {
+                self.download_segment(raw_url, &part_path, range, validator.as_ref())
+            }))
+            .await;
+
+        // A preallocated sidecar cannot be resumed, drop it
+        if let Err(e) = result {
+            let _ = fs::remove_file(&part_path).await;
+            return Err(e);
+        }
+
+        if let Some(checksum_obj) = checksum &&
+            !self
+                .verify_checksum(checksum_obj, &part_path, m_pb.clone())
+                .await?
+        {
+            let _ = fs::remove_file(&part_path).await;
+            return Err(anyhow!(
+                "Checksum mismatch. Downloaded file does not match the expected hash."
+            ));
+        }
+
+        fs::rename(&part_path, local_path).await?;
+        self.hash_cache().rename(&part_path, local_path);
+        // A single-stream sidecar left by an earlier attempt is stale now
+        let _ = fs::remove_file(file_ops::part_path(local_path)).await;
+        let _ = fs::remove_file(validator_path(local_path)).await;
+        Ok(true)
+    }
+}
@@ -0,0 +1,291 @@
+//! Safety limits and trash handling for pruned local files.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use tokio::fs;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use super::file_ops::ensure_parent_dir;
+
+/// Default trash directory name, created inside the local path
+pub const DEFAULT_TRASH_DIR: &str = ".alist_trash";
+
+/// Name format of a trash batch directory, one per pruning run; the
+/// microseconds keep runs started within the same second apart
+const BATCH_FORMAT: &str = "%Y-%m-%d_%H-%M-%S%.6f";
+
+/// Upper bound on how many files a single pruning run may remove
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeleteLimit {
+    /// Absolute number of files
+    Count(usize),
+    /// Percentage of the local files under the pruned folder
+    Percent(f64),
+}
+
+impl DeleteLimit {
+    /// Checks if removing `to_delete` out of `total` files exceeds the limit
+    ///
+    /// # Arguments
+    ///
+    /// * `to_delete` - Number of files that would be removed
+    /// * `total` - Number of local files considered for pruning
+    ///
+    /// # Returns
+    ///
+    /// `true` if the run should be aborted
+    pub fn is_exceeded(&self, to_delete: usize, total: usize) -> bool {
+        match *self {
+            DeleteLimit::Count(max) => to_delete > max,
+            DeleteLimit::Percent(max) => {
+                total > 0 && (to_delete as f64 / total as f64) * 100.0 > max
+            }
+        }
+    }
+}
+
+impl FromStr for DeleteLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            let value: f64 = percent
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid percentage '{}': {}", s, e))?;
+            if !(0.0..=100.0).contains(&value) {
+                return Err(anyhow!("Percentage must be between 0 and 100: '{}'", s));
+            }
+            Ok(DeleteLimit::Percent(value))
+        } else {
+            let value = s
+                .parse()
+                .map_err(|e| anyhow!("Invalid file count '{}': {}", s, e))?;
+            Ok(DeleteLimit::Count(value))
+        }
+    }
+}
+
+impl fmt::Display for DeleteLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeleteLimit::Count(max) => write!(f, "{max} files"),
+            DeleteLimit::Percent(max) => write!(f, "{max}%"),
+        }
+    }
+}
+
+/// Trash directory holding pruned files in dated batches.
+///
+/// Each pruning run moves its files into `<root>/<timestamp>/`, keeping
+/// their path relative to the local directory so they can be restored.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    root: PathBuf,
+}
+
+impl Trash {
+    /// Creates a trash handle rooted at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the trash root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Creates the directory for a new batch named after the current time
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created, or already exists
+    /// because another run started at the same time
+    pub async fn new_batch(&self) -> Result<PathBuf> {
+        let batch_dir = self
+            .root
+            .join(Local::now().format(BATCH_FORMAT).to_string());
+        fs::create_dir_all(&self.root).await?;
+        fs::create_dir(&batch_dir).await.map_err(|e| {
+            anyhow!(
+                "Failed to create trash batch '{}': {}",
+                batch_dir.display(),
+                e
+            )
+        })?;
+        Ok(batch_dir)
+    }
+
+    /// Moves a local file into a trash batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_dir` - Batch directory returned by [`Trash::new_batch`]
+    /// * `local_root` - Local directory the file path is relative to
+    /// * `file` - The file to move
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is outside `local_root` or cannot be moved
+    pub async fn move_file(batch_dir: &Path, local_root: &Path, file: &Path) -> Result<()> {
+        let rel_path = file.strip_prefix(local_root).map_err(|_| {
+            anyhow!(
+                "'{}' is not under '{}'",
+                file.display(),
+                local_root.display()
+            )
+        })?;
+        let target = batch_dir.join(rel_path);
+        move_path(file, &target).await?;
+        debug!("Moved {} to {}", file.display(), target.display());
+        Ok(())
+    }
+
+    /// Lists the existing batches, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trash directory cannot be read
+    pub async fn batches(&self) -> Result<Vec<String>> {
+        let mut batches = Vec::new();
+        if !self.root.exists() {
+            return Ok(batches);
+        }
+
+        let mut dir = fs::read_dir(&self.root).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                batches.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        batches.sort();
+        Ok(batches)
+    }
+
+    /// Moves every file of a batch back into the local directory.
+    ///
+    /// Files that already exist locally are left in the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - Name of the batch to restore
+    /// * `local_root` - Local directory to restore into
+    ///
+    /// # Returns
+    ///
+    /// The number of restored files
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the batch does not exist or a move fails
+    pub async fn restore(&self, batch: &str, local_root: &Path) -> Result<usize> {
+        let batch_dir = self.root.join(batch);
+        if !batch_dir.is_dir() {
+            return Err(anyhow!("Trash batch '{}' does not exist", batch));
+        }
+
+        let mut restored = 0;
+        let files: Vec<PathBuf> = WalkDir::new(&batch_dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect();
+
+        for file in files {
+            let rel_path = file.strip_prefix(&batch_dir)?;
+            let target = local_root.join(rel_path);
+            if target.exists() {
+                warn!("Not restoring {}: file already exists", target.display());
+                continue;
+            }
+            move_path(&file, &target).await?;
+            info!("Restored {}", target.display());
+            restored += 1;
+        }
+
+        remove_empty_dirs(&batch_dir).await;
+        Ok(restored)
+    }
+
+    /// Permanently deletes trash batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` - Only purge batches older than this, or every batch if
+    ///   `None`
+    ///
+    /// # Returns
+    ///
+    /// The names of the purged batches
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a batch cannot be removed
+    pub async fn purge(&self, older_than: Option<chrono::Duration>) -> Result<Vec<String>> {
+        let now = Local::now();
+        let mut purged = Vec::new();
+
+        for batch in self.batches().await? {
+            if let Some(age) = older_than {
+                let Some(created) = parse_batch_time(&batch) else {
+                    warn!("Skipping unrecognized trash entry: {}", batch);
+                    continue;
+                };
+                if now - created < age {
+                    continue;
+                }
+            }
+            fs::remove_dir_all(self.root.join(&batch)).await?;
+            info!("Purged trash batch {}", batch);
+            purged.push(batch);
+        }
+
+        Ok(purged)
+    }
+}
+
+/// Parses the creation time from a batch directory name
+fn parse_batch_time(batch: &str) -> Option<DateTime<Local>> {
+    let naive = NaiveDateTime::parse_from_str(batch, BATCH_FORMAT).ok()?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Moves a file, falling back to copy and delete across filesystems
+async fn move_path(from: &Path, to: &Path) -> Result<()> {
+    ensure_parent_dir(to).await?;
+    match fs::rename(from, to).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to).await?;
+            fs::remove_file(from).await?;
+            Ok(())
+        }
+        Err(e) => Err(anyhow!(
+            "Failed to move '{}' to '{}': {}",
+            from.display(),
+            to.display(),
+            e
+        )),
+    }
+}
+
+/// Removes every empty directory under `root`, including `root` itself
+async fn remove_empty_dirs(root: &Path) {
+    for entry in WalkDir::new(root)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        let _ = fs::remove_dir(entry.path()).await;
+    }
+}
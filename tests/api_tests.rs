@@ -1,6 +1,12 @@
 //! Tests for API functionality.
 
-use alist_cli::api::types::{HashObject, is_metadata_file, is_streamable_file};
+use alist_cli::{
+    AlistClient, Config,
+    api::{
+        operations::build_sign_url,
+        types::{HashObject, is_metadata_file, is_path_within, is_streamable_file},
+    },
+};
 
 #[test]
 fn test_hash_object_as_hash_str() {
@@ -39,7 +45,87 @@ fn test_file_extension_edge_cases() {
     assert!(!is_metadata_file(""));
     assert!(!is_streamable_file(""));
 
-    // Test case sensitivity - should be case sensitive
-    assert!(!is_metadata_file("JPG"));
-    assert!(!is_streamable_file("MP4"));
+    // Extensions compare case-insensitively
+    assert!(is_metadata_file("JPG"));
+    assert!(is_streamable_file("MP4"));
+}
+
+#[test]
+fn test_is_path_within() {
+    assert!(is_path_within("/movies/a.mkv", "/movies"));
+    assert!(is_path_within("/movies/a.mkv", "/movies/"));
+    assert!(is_path_within("/movies", "/movies"));
+    assert!(is_path_within("/movies/a.mkv", "/"));
+
+    // Sibling directories sharing a prefix are not nested
+    assert!(!is_path_within("/movies2/a.mkv", "/movies"));
+    assert!(!is_path_within("/tv/a.mkv", "/movies"));
+}
+
+#[test]
+fn test_build_sign_url() {
+    assert_eq!(
+        build_sign_url(
+            "http://localhost:5244/",
+            "d",
+            "/movies/My Film (2020)/film.mkv",
+            "abc=:0"
+        ),
+        "http://localhost:5244/d/movies/My%20Film%20(2020)/film.mkv?sign=abc%3D%3A0"
+    );
+
+    // No query string when signing is disabled
+    assert_eq!(
+        build_sign_url("http://localhost:5244", "p", "/tv/#1/電視.mp4", ""),
+        "http://localhost:5244/p/tv/%231/%E9%9B%BB%E8%A6%96.mp4"
+    );
+}
+
+#[test]
+fn test_clients_keep_separate_config() {
+    let home = AlistClient::new(Config {
+        server_address: "http://home:5244".to_string(),
+        ..Config::default_test_config()
+    })
+    .unwrap();
+    let office = AlistClient::new(Config {
+        server_address: "http://office:5244".to_string(),
+        threads: 8,
+        ..Config::default_test_config()
+    })
+    .unwrap();
+
+    assert_eq!(home.api_url("/api/fs/list"), "http://home:5244/api/fs/list");
+    assert_eq!(
+        office.api_url("/api/fs/list"),
+        "http://office:5244/api/fs/list"
+    );
+    assert_eq!(home.config().threads, 4);
+    assert_eq!(office.config().threads, 8);
+}
+
+#[tokio::test]
+async fn test_download_waits_for_headers_up_to_read_timeout() {
+    // Accepts connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/d/movie.mkv", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let client = AlistClient::new(Config {
+        read_timeout: 1,
+        ..Config::default_test_config()
+    })
+    .unwrap();
+    let started = std::time::Instant::now();
+    let err = client
+        .rate_limited_get(&url, reqwest::header::HeaderMap::new())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No response received"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
 }
@@ -0,0 +1,63 @@
+//! Tests for login helpers.
+
+use alist_cli::{
+    Config,
+    api::auth::{hash_password, is_auth_failure, save_token},
+};
+use reqwest::StatusCode;
+
+#[test]
+fn test_hash_password() {
+    // Same value the AList web UI sends for the password "admin"
+    assert_eq!(
+        hash_password("admin"),
+        "6fcb57cd10b2c11d765dcf16148d99130afd895082af83725ee8bb181b1d2b0f"
+    );
+}
+
+#[test]
+fn test_is_auth_failure() {
+    assert!(is_auth_failure(StatusCode::UNAUTHORIZED, b""));
+    assert!(is_auth_failure(
+        StatusCode::OK,
+        br#"{"code":401,"message":"token is expired","data":null}"#
+    ));
+    assert!(!is_auth_failure(
+        StatusCode::OK,
+        br#"{"code":200,"message":"success","data":null}"#
+    ));
+    assert!(!is_auth_failure(StatusCode::OK, b"not json"));
+}
+
+#[tokio::test]
+async fn test_save_token() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("alist_cli/token");
+    save_token(&path, "first").await.unwrap();
+    save_token(&path, "second").await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        // An existing file readable by others is replaced by a private one
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        save_token(&path, "third").await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn test_config_debug_redacts_secrets() {
+    let config = Config {
+        token: "secret-token".to_string(),
+        username: Some("admin".to_string()),
+        password: Some("secret-password".to_string()),
+        ..Config::default_test_config()
+    };
+    let debug = format!("{config:?}");
+    assert!(debug.contains("admin"));
+    assert!(!debug.contains("secret"));
+}
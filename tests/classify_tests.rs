@@ -0,0 +1,72 @@
+//! Tests for file classification rules.
+
+use alist_cli::classify::{Classifier, ClassifyRule, FileAction};
+
+fn rule(
+    extensions: &[&str],
+    glob: Option<&str>,
+    mime: Option<&str>,
+    action: FileAction,
+) -> ClassifyRule {
+    ClassifyRule {
+        extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+        glob: glob.map(str::to_string),
+        mime: mime.map(str::to_string),
+        action,
+    }
+}
+
+#[test]
+fn test_default_lists_ignore_case() {
+    let classifier = Classifier::default();
+    assert_eq!(classifier.classify("/a/Movie.MKV"), Some(FileAction::Strm));
+    assert_eq!(
+        classifier.classify("/a/movie.zh.ass"),
+        Some(FileAction::Copy)
+    );
+    assert_eq!(classifier.classify("/a/readme"), None);
+    // Blu-ray folder index files are copied alongside the streams
+    assert_eq!(
+        classifier.classify("/a/BDMV/index.bdmv"),
+        Some(FileAction::Copy)
+    );
+    assert_eq!(
+        classifier.classify("/a/BDMV/MovieObject.BDMV"),
+        Some(FileAction::Copy)
+    );
+}
+
+#[test]
+fn test_rules_take_precedence_in_order() {
+    let classifier = Classifier::new(
+        &[
+            rule(
+                &[],
+                Some("/music/**"),
+                Some("audio/*"),
+                FileAction::Download,
+            ),
+            rule(&["ISO"], None, None, FileAction::Ignore),
+        ],
+        &["mkv".to_string(), "flac".to_string()],
+        &["nfo".to_string()],
+    )
+    .unwrap();
+
+    assert_eq!(
+        classifier.classify("/music/x/a.flac"),
+        Some(FileAction::Download)
+    );
+    assert_eq!(
+        classifier.classify("/movies/a.flac"),
+        Some(FileAction::Strm)
+    );
+    assert_eq!(
+        classifier.classify("/movies/a.iso"),
+        Some(FileAction::Ignore)
+    );
+    assert_eq!(classifier.classify("/music/a.nfo"), Some(FileAction::Copy));
+
+    // A rule without criteria would match everything
+    assert!(Classifier::new(&[rule(&[], None, None, FileAction::Strm)], &[], &[]).is_err());
+}
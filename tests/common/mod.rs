@@ -0,0 +1,264 @@
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, path::Path, process::Output, sync::Arc};
+
+use alist_cli::api::types::{EntryInfo, EntryWithPath};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    process::Command,
+};
+
+/// Builds a listing entry as the server would return it
+///
+/// # Arguments
+///
+/// * `path` - Full remote path, the name is its last component
+/// * `is_dir` - Whether the entry is a directory
+/// * `size` - Size in bytes
+/// * `modified` - Modification time as sent by the server
+pub fn entry(path: &str, is_dir: bool, size: u64, modified: &str) -> EntryWithPath {
+    EntryWithPath {
+        entry: EntryInfo {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            size,
+            is_dir,
+            modified: modified.to_string(),
+            sign: String::new(),
+            thumb: String::new(),
+            file_type: 0,
+            created: None,
+            hashinfo: None,
+            hash_info: None,
+        },
+        path_str: path.to_string(),
+        provider: "Local".to_string(),
+    }
+}
+
+/// Request received by a fake server
+pub struct FakeRequest {
+    pub method: String,
+    pub path: String,
+    /// Header values keyed by lowercase name
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Response sent by a fake server
+pub struct FakeResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Serves HTTP on a local port, answering every request with `handler`
+///
+/// # Returns
+///
+/// The server address for the client's config
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(FakeRequest) -> FakeResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(answer(stream, Arc::clone(&handler)));
+        }
+    });
+    address
+}
+
+/// Answers the requests of one keep-alive connection
+async fn answer<F>(stream: TcpStream, handler: Arc<F>)
+where
+    F: Fn(FakeRequest) -> FakeResponse,
+{
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let mut request_line = line.split_whitespace();
+        let method = request_line.next().unwrap_or_default().to_string();
+        let path = request_line.next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                break;
+            };
+            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let content_length = headers
+            .get("content-length")
+            .map_or(0, |length| length.parse().unwrap());
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await.unwrap();
+
+        let is_head = method == "HEAD";
+        let response = handler(FakeRequest {
+            method,
+            path,
+            headers,
+            body,
+        });
+        let mut head = format!("HTTP/1.1 {} Fake\r\n", response.status);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", response.body.len()));
+        let stream = stream.get_mut();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        if !is_head {
+            stream.write_all(&response.body).await.unwrap();
+        }
+    }
+}
+
+impl FakeResponse {
+    /// Answers with a JSON body, as the AList API does
+    pub fn json(body: serde_json::Value) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+/// Answers an `/api/fs/list` request from fixed listings
+///
+/// Directories missing from `listings` are answered with API error 404.
+///
+/// # Arguments
+///
+/// * `listings` - Entries of each remote directory, keyed by its path
+/// * `request` - The list request
+pub fn list_response(
+    listings: &HashMap<String, Vec<EntryWithPath>>,
+    request: &FakeRequest,
+) -> FakeResponse {
+    let request: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    FakeResponse::json(match listings.get(request["path"].as_str().unwrap()) {
+        Some(entries) => serde_json::json!({
+            "code": 200,
+            "message": "success",
+            "data": {
+                "content": entries.iter().map(|e| &e.entry).collect::<Vec<_>>(),
+                "total": entries.len(),
+                "readme": "",
+                "write": false,
+                "provider": "Local",
+                "header": "",
+            },
+        }),
+        None => serde_json::json!({ "code": 404, "message": "not found", "data": null }),
+    })
+}
+
+/// Serves `/api/fs/list` from fixed listings on a local port, see
+/// [`list_response`]
+///
+/// # Returns
+///
+/// The server address for the client's config
+pub async fn serve_listings(listings: HashMap<String, Vec<EntryWithPath>>) -> String {
+    serve(move |request| list_response(&listings, &request)).await
+}
+
+/// Serves one file at every path, honoring `Range` and `If-Range`
+///
+/// Ranges may be open (`bytes=10-`) or bounded (`bytes=10-19`); a range
+/// starting at or past the end is answered with 416.
+///
+/// # Arguments
+///
+/// * `content` - The file contents
+/// * `etag` - `ETag` of the file
+///
+/// # Returns
+///
+/// The server address
+pub async fn serve_file(content: &[u8], etag: &str) -> String {
+    let content = content.to_vec();
+    let etag = etag.to_string();
+    serve(move |request| {
+        let range = request
+            .headers
+            .get("range")
+            .filter(|_| {
+                request
+                    .headers
+                    .get("if-range")
+                    .is_none_or(|tag| *tag == etag)
+            })
+            .and_then(|range| range.strip_prefix("bytes=")?.split_once('-'))
+            .map(|(start, end)| {
+                let start: usize = start.parse().unwrap();
+                let end = end.parse().map_or(content.len(), |end: usize| end + 1);
+                (start, end.min(content.len()))
+            });
+        let headers = vec![
+            ("ETag", etag.clone()),
+            ("Accept-Ranges", "bytes".to_string()),
+        ];
+        match range {
+            None => FakeResponse {
+                status: 200,
+                headers,
+                body: content.clone(),
+            },
+            Some((start, _)) if start >= content.len() => FakeResponse {
+                status: 416,
+                headers,
+                body: Vec::new(),
+            },
+            Some((start, end)) => {
+                let mut headers = headers;
+                headers.push((
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end - 1, content.len()),
+                ));
+                FakeResponse {
+                    status: 206,
+                    headers,
+                    body: content[start..end].to_vec(),
+                }
+            }
+        }
+    })
+    .await
+}
+
+/// Runs the `alist_cli` binary with a clean environment
+///
+/// # Arguments
+///
+/// * `home` - Directory used as `HOME`, so no config or cache of the user is
+///   read
+/// * `args` - Command line arguments
+///
+/// # Returns
+///
+/// The output of the finished process
+pub async fn run_cli(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_alist_cli"))
+        .args(args)
+        .env_clear()
+        .env("HOME", home)
+        .env("RUST_LOG", "info")
+        .output()
+        .await
+        .unwrap()
+}
@@ -0,0 +1,72 @@
+//! Tests for the configuration file and profile layering.
+
+use alist_cli::{
+    classify::FileAction,
+    config::{ConfigFile, DEFAULT_SERVER_ADDRESS, Profile},
+};
+
+const CONFIG: &str = r#"
+default_profile = "home"
+
+[profiles.home]
+server_address = "http://home:5244"
+token = "home-token"
+threads = 8
+local_path = "/media/strm"
+strm_extensions = ["mkv", "webm"]
+
+[profiles.remote]
+server_address = "https://remote.example.com"
+tpslimit = 5
+"#;
+
+#[test]
+fn test_profile_selection() {
+    let file = ConfigFile::parse(CONFIG).unwrap();
+
+    let home = file.profile(None).unwrap();
+    assert_eq!(home.server_address.as_deref(), Some("http://home:5244"));
+
+    let remote = file.profile(Some("remote")).unwrap();
+    assert_eq!(remote.tpslimit, Some(5));
+
+    assert!(file.profile(Some("missing")).is_err());
+}
+
+#[test]
+fn test_empty_config_uses_defaults() {
+    let file = ConfigFile::parse("").unwrap();
+    let config = file.profile(None).unwrap().to_config().unwrap();
+    assert_eq!(config.server_address, DEFAULT_SERVER_ADDRESS);
+    assert_eq!(config.threads, 4);
+    assert_eq!(config.concurrent_limit, 10);
+    assert_eq!(config.classify("/a.mkv"), Some(FileAction::Strm));
+}
+
+#[test]
+fn test_unknown_fields_rejected() {
+    assert!(ConfigFile::parse("[profiles.home]\nserver = \"x\"").is_err());
+}
+
+#[test]
+fn test_merge_precedence() {
+    let file = ConfigFile::parse(CONFIG).unwrap();
+    let overrides = Profile {
+        token: Some("cli-token".to_string()),
+        threads: Some(16),
+        ..Profile::default()
+    };
+    let config = file
+        .profile(None)
+        .unwrap()
+        .merge(overrides)
+        .to_config()
+        .unwrap();
+
+    assert_eq!(config.server_address, "http://home:5244");
+    assert_eq!(config.token, "cli-token");
+    assert_eq!(config.threads, 16);
+    assert_eq!(config.concurrent_limit, 16);
+    assert_eq!(config.classify("/a.webm"), Some(FileAction::Strm));
+    assert_eq!(config.classify("/a.mp4"), None);
+}
@@ -0,0 +1,246 @@
+//! Tests for download file helpers.
+
+use std::path::Path;
+
+use alist_cli::{
+    AlistClient, Config,
+    api::types::HashObject,
+    utils::{
+        file_ops::{parse_content_range_start, part_path, set_modified, write_if_changed},
+        hash_cache::HashCache,
+        segmented::{segment_part_path, split_ranges},
+    },
+};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+
+use common::serve_file;
+
+mod common;
+
+#[test]
+fn test_part_path() {
+    assert_eq!(
+        part_path(Path::new("/data/movie.mkv")),
+        Path::new("/data/movie.mkv.part")
+    );
+}
+
+#[test]
+fn test_segment_part_path() {
+    // Apart from the single-stream sidecar, which must never see its holes
+    assert_eq!(
+        segment_part_path(Path::new("/data/movie.mkv")),
+        Path::new("/data/movie.mkv.segpart")
+    );
+}
+
+#[test]
+fn test_parse_content_range_start() {
+    assert_eq!(parse_content_range_start("bytes 100-999/1000"), Some(100));
+    assert_eq!(parse_content_range_start("bytes 0-0/*"), Some(0));
+    assert_eq!(parse_content_range_start("bytes */1000"), None);
+    assert_eq!(parse_content_range_start("items 1-2/3"), None);
+}
+
+#[test]
+fn test_split_ranges() {
+    assert_eq!(split_ranges(10, 3), vec![(0, 3), (4, 7), (8, 9)]);
+    assert_eq!(split_ranges(4, 4), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+    // Never more ranges than bytes
+    assert_eq!(split_ranges(2, 8), vec![(0, 0), (1, 1)]);
+    assert!(split_ranges(0, 4).is_empty());
+}
+
+#[tokio::test]
+async fn test_write_if_changed_keeps_unchanged_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let path = root.join("movies/a.strm");
+
+    assert!(write_if_changed(&path, b"http://a").await.unwrap());
+    set_modified(&path, "2024-01-01T00:00:00Z").await.unwrap();
+    assert!(!write_if_changed(&path, b"http://a").await.unwrap());
+    let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert_eq!(
+        chrono::DateTime::<chrono::Utc>::from(mtime).to_rfc3339(),
+        "2024-01-01T00:00:00+00:00"
+    );
+
+    assert!(write_if_changed(&path, b"http://b").await.unwrap());
+    assert_eq!(std::fs::read(&path).unwrap(), b"http://b");
+}
+
+#[tokio::test]
+async fn test_hash_cache_invalidated_by_change() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let file = root.join("movie.mkv");
+    let cache_path = root.join("hashes.json");
+    let sha1 = HashObject::Sha1 {
+        sha1: String::new(),
+    };
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(&file, b"first").unwrap();
+
+    let cache = HashCache::load(Some(cache_path.clone()));
+    let metadata = std::fs::metadata(&file).unwrap();
+    cache.insert(&file, &metadata, &sha1, "digest".to_string());
+    cache.save().await.unwrap();
+
+    // Survives a reload, but only for the digest that was computed
+    let cache = HashCache::load(Some(cache_path));
+    assert_eq!(
+        cache.get(&file, &metadata, &sha1),
+        Some("digest".to_string())
+    );
+    let md5 = HashObject::Md5 { md5: String::new() };
+    assert_eq!(cache.get(&file, &metadata, &md5), None);
+
+    std::fs::write(&file, b"second, longer").unwrap();
+    let metadata = std::fs::metadata(&file).unwrap();
+    assert_eq!(cache.get(&file, &metadata, &sha1), None);
+}
+
+#[tokio::test]
+async fn test_hash_cache_drops_stale_entries() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let cache_path = root.join("hashes.json");
+    let sha1 = HashObject::Sha1 {
+        sha1: String::new(),
+    };
+    let cache = HashCache::load(Some(cache_path.clone()));
+    for name in ["kept.mkv", "changed.mkv", "deleted.mkv"] {
+        let file = root.join(name);
+        std::fs::write(&file, b"first").unwrap();
+        let metadata = std::fs::metadata(&file).unwrap();
+        cache.insert(&file, &metadata, &sha1, "digest".to_string());
+    }
+    cache.save().await.unwrap();
+
+    std::fs::write(root.join("changed.mkv"), b"second, longer").unwrap();
+    std::fs::remove_file(root.join("deleted.mkv")).unwrap();
+    // Saving an otherwise unchanged cache still drops the stale entries
+    let cache = HashCache::load(Some(cache_path.clone()));
+    cache.save().await.unwrap();
+
+    let saved: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&cache_path).unwrap()).unwrap();
+    let keys: Vec<_> = saved.keys().collect();
+    assert_eq!(keys, vec![&root.join("kept.mkv").to_string_lossy()]);
+}
+
+/// Downloads `url` to `movie.mkv` in `root`, after leaving a sidecar with
+/// `part` and, if given, the stored `etag`
+async fn resume_download(root: &Path, url: &str, part: &[u8], etag: Option<&str>, size: u64) {
+    let local_path = root.join("movie.mkv");
+    let part_path = part_path(&local_path);
+    std::fs::write(&part_path, part).unwrap();
+    if let Some(etag) = etag {
+        let mut validator_path = part_path.into_os_string();
+        validator_path.push(".etag");
+        std::fs::write(validator_path, etag).unwrap();
+    }
+
+    let client = AlistClient::new(Config::default_test_config()).unwrap();
+    let m_pb = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    client
+        .download_file_with_retries(url, &local_path, None, size, m_pb)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_download_discards_part_without_validator() {
+    let tmp = tempfile::tempdir().unwrap();
+    let url = serve_file(b"0123456789", "\"v1\"").await;
+
+    // Nothing tells whether the sidecar was cut from this version of the file
+    resume_download(tmp.path(), &url, b"XXXX", None, 10).await;
+    assert_eq!(
+        std::fs::read(tmp.path().join("movie.mkv")).unwrap(),
+        b"0123456789"
+    );
+}
+
+#[tokio::test]
+async fn test_download_resumes_part_with_validator() {
+    let tmp = tempfile::tempdir().unwrap();
+    let url = serve_file(b"0123456789", "\"v1\"").await;
+
+    resume_download(tmp.path(), &url, b"0123", Some("\"v1\""), 10).await;
+    assert_eq!(
+        std::fs::read(tmp.path().join("movie.mkv")).unwrap(),
+        b"0123456789"
+    );
+}
+
+#[tokio::test]
+async fn test_download_finishes_complete_part() {
+    let tmp = tempfile::tempdir().unwrap();
+    // Nothing listens on the discard port, so any request would fail
+    let url = "http://127.0.0.1:9/movie.mkv";
+
+    resume_download(tmp.path(), url, b"0123456789", Some("\"v1\""), 10).await;
+    assert_eq!(
+        std::fs::read(tmp.path().join("movie.mkv")).unwrap(),
+        b"0123456789"
+    );
+    assert!(!part_path(&tmp.path().join("movie.mkv")).exists());
+}
+
+#[tokio::test]
+async fn test_download_restarts_on_rejected_range() {
+    let tmp = tempfile::tempdir().unwrap();
+    let url = serve_file(b"0123456789", "\"v1\"").await;
+
+    // Without a known size the complete sidecar is only found out by the 416
+    resume_download(tmp.path(), &url, b"0123456789", Some("\"v1\""), 0).await;
+    assert_eq!(
+        std::fs::read(tmp.path().join("movie.mkv")).unwrap(),
+        b"0123456789"
+    );
+}
+
+#[tokio::test]
+async fn test_segmented_download() {
+    let tmp = tempfile::tempdir().unwrap();
+    let local_path = tmp.path().join("movie.mkv");
+    let url = serve_file(b"0123456789", "\"v1\"").await;
+    // A stale single-stream sidecar is dropped once the file is complete
+    std::fs::write(part_path(&local_path), b"XXXX").unwrap();
+
+    let client = AlistClient::new(Config::default_test_config()).unwrap();
+    let m_pb = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    assert!(
+        client
+            .download_segmented(&url, &local_path, None, 10, 3, m_pb)
+            .await
+            .unwrap()
+    );
+    assert_eq!(std::fs::read(&local_path).unwrap(), b"0123456789");
+    assert!(!part_path(&local_path).exists());
+    assert!(!segment_part_path(&local_path).exists());
+}
+
+#[tokio::test]
+async fn test_segmented_download_falls_back_if_probe_fails() {
+    let tmp = tempfile::tempdir().unwrap();
+    let local_path = tmp.path().join("movie.mkv");
+
+    let client = AlistClient::new(Config::default_test_config()).unwrap();
+    let m_pb = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let segmented = client
+        .download_segmented(
+            "http://127.0.0.1:9/movie.mkv",
+            &local_path,
+            None,
+            10,
+            3,
+            m_pb,
+        )
+        .await
+        .unwrap();
+    assert!(!segmented);
+    assert!(!segment_part_path(&local_path).exists());
+}
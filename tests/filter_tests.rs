@@ -0,0 +1,84 @@
+//! Tests for include/exclude filtering.
+
+use alist_cli::filter::{FilterRules, PathFilter, parse_date, parse_size};
+
+use common::entry;
+
+mod common;
+
+#[test]
+fn test_globs_match_name_or_path() {
+    let filter = PathFilter::new(FilterRules {
+        include: vec!["*.mkv".to_string()],
+        exclude: vec!["/movies/extras".to_string()],
+        exclude_regex: vec![r"(?i)sample".to_string()],
+        ..FilterRules::default()
+    })
+    .unwrap();
+    let t = "2024-01-01T00:00:00Z";
+
+    assert!(filter.allows(&entry("/movies/a.mkv", false, 1, t)));
+    assert!(!filter.allows(&entry("/movies/a.nfo", false, 1, t)));
+    assert!(!filter.allows(&entry("/movies/a.Sample.mkv", false, 1, t)));
+    // Include rules do not stop directories from being listed
+    assert!(filter.allows(&entry("/movies/b", true, 0, t)));
+    assert!(!filter.allows(&entry("/movies/extras", true, 0, t)));
+
+    assert!(filter.allows_path("/movies/a.mkv"));
+    assert!(!filter.allows_path("/movies/extras/a.mkv"));
+}
+
+#[test]
+fn test_size_and_date_predicates() {
+    let filter = PathFilter::new(FilterRules {
+        min_size: Some("1M".to_string()),
+        modified_after: Some("2024-01-01".to_string()),
+        ..FilterRules::default()
+    })
+    .unwrap();
+
+    assert!(filter.allows(&entry(
+        "/a.mkv",
+        false,
+        1 << 20,
+        "2024-01-01T08:00:00+08:00"
+    )));
+    assert!(!filter.allows(&entry("/a.mkv", false, 1 << 20, "2023-12-31T23:59:59Z")));
+    assert!(!filter.allows(&entry("/a.mkv", false, 1000, "2024-06-01T00:00:00Z")));
+    // Local files are checked the same way, and pass without a date
+    assert!(filter.allows_file("/a.mkv", 1 << 20, None));
+    assert!(!filter.allows_file("/a.mkv", 1000, None));
+    assert_eq!(parse_size("1.5G").unwrap(), 3 << 29);
+    assert!(parse_size("10X").is_err());
+}
+
+#[test]
+fn test_local_file_predicates() {
+    // Local copies outside the size or date range must stay out of scope, or
+    // pruning would delete them
+    let filter = PathFilter::new(FilterRules {
+        min_size: Some("1K".to_string()),
+        modified_after: Some("2024-01-01".to_string()),
+        ..FilterRules::default()
+    })
+    .unwrap();
+    assert!(filter.has_size_limits());
+    assert!(!PathFilter::default().has_size_limits());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("a.mkv");
+    let file = std::fs::File::create(&path).unwrap();
+    let new = parse_date("2024-06-01").unwrap().into();
+    let old = parse_date("2023-06-01").unwrap().into();
+
+    file.set_len(1 << 10).unwrap();
+    file.set_modified(new).unwrap();
+    assert!(filter.allows_local_file("/a.mkv", &path.metadata().unwrap()));
+
+    file.set_modified(old).unwrap();
+    assert!(!filter.allows_local_file("/a.mkv", &path.metadata().unwrap()));
+
+    file.set_len(10).unwrap();
+    file.set_modified(new).unwrap();
+    assert!(!filter.allows_local_file("/a.mkv", &path.metadata().unwrap()));
+}
@@ -0,0 +1,16 @@
+//! Tests for the run lock.
+
+use alist_cli::utils::lock::RunLock;
+
+#[test]
+fn test_run_lock_is_exclusive() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+
+    let lock = RunLock::try_acquire(&root).unwrap();
+    assert!(lock.is_some());
+    assert!(RunLock::try_acquire(&root).unwrap().is_none());
+
+    drop(lock);
+    assert!(RunLock::try_acquire(&root).unwrap().is_some());
+}
@@ -0,0 +1,105 @@
+//! Tests for remote path helpers of the file management commands.
+
+use alist_cli::{
+    api::{
+        manage::{
+            check_renames, group_by_dir, parse_rename_list, regex_renames, split_remote_path,
+        },
+        types::RenameObject,
+    },
+    utils::rename_journal::{RenameBatch, RenameJournal},
+};
+use regex::Regex;
+
+fn rename(src_name: &str, new_name: &str) -> RenameObject {
+    RenameObject {
+        src_name: src_name.to_string(),
+        new_name: new_name.to_string(),
+    }
+}
+
+#[test]
+fn test_split_remote_path() {
+    assert_eq!(
+        split_remote_path("/incoming/*.mkv").unwrap(),
+        ("/incoming", "*.mkv")
+    );
+    assert_eq!(split_remote_path("/movies/").unwrap(), ("/", "movies"));
+    assert!(split_remote_path("/").is_err());
+    assert!(split_remote_path("movies").is_err());
+}
+
+#[test]
+fn test_group_by_dir() {
+    let groups = group_by_dir(["/a/x.mkv", "/b/y.mkv", "/a/z.nfo", "/top"]).unwrap();
+    let groups: Vec<_> = groups.into_iter().collect();
+    assert_eq!(
+        groups,
+        vec![
+            ("/".to_string(), vec!["top".to_string()]),
+            (
+                "/a".to_string(),
+                vec!["x.mkv".to_string(), "z.nfo".to_string()]
+            ),
+            ("/b".to_string(), vec!["y.mkv".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn test_regex_renames_are_checked() {
+    let names = [
+        "Show.S01E01.1080p.mkv",
+        "Show.S01E02.1080p.mkv",
+        "notes.txt",
+    ];
+    let regex = Regex::new(r"^Show\.S(\d+)E(\d+)\.1080p").unwrap();
+    let renames = regex_renames(names, &regex, "Show - ${1}x${2}");
+    assert_eq!(
+        renames,
+        vec![
+            rename("Show.S01E01.1080p.mkv", "Show - 01x01.mkv"),
+            rename("Show.S01E02.1080p.mkv", "Show - 01x02.mkv"),
+        ]
+    );
+    assert!(check_renames(names, &renames).is_ok());
+
+    // Both episodes would get the same name
+    let renames = regex_renames(names, &regex, "Show");
+    assert!(check_renames(names, &renames).is_err());
+    // The new name is taken by an entry that is not renamed
+    assert!(check_renames(names, &[rename("notes.txt", "Show.S01E01.1080p.mkv")]).is_err());
+    assert!(check_renames(names, &[rename("missing.txt", "new.txt")]).is_err());
+    assert!(check_renames(names, &[rename("notes.txt", "a/b.txt")]).is_err());
+}
+
+#[test]
+fn test_parse_rename_list() {
+    let renames = parse_rename_list("a b.mkv\tc.mkv\n\nd.nfo\te.nfo\n").unwrap();
+    assert_eq!(
+        renames,
+        vec![rename("a b.mkv", "c.mkv"), rename("d.nfo", "e.nfo")]
+    );
+    assert!(parse_rename_list("no tab here").is_err());
+}
+
+#[tokio::test]
+async fn test_rename_journal_undo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("renames.json");
+    let journal = RenameJournal::new(&path);
+    assert!(journal.last("http://a").await.unwrap().is_none());
+
+    let first = RenameBatch::new("http://a", "/tv", vec![rename("x.mkv", "y.mkv")]);
+    let other = RenameBatch::new("http://b", "/tv", vec![rename("p.mkv", "q.mkv")]);
+    journal.record(first.clone()).await.unwrap();
+    journal.record(other).await.unwrap();
+
+    let last = journal.last("http://a").await.unwrap().unwrap();
+    assert_eq!(last, first);
+    assert_eq!(last.reverted(), vec![rename("y.mkv", "x.mkv")]);
+
+    journal.forget(&last).await.unwrap();
+    assert!(journal.last("http://a").await.unwrap().is_none());
+    assert_eq!(journal.batches().await.unwrap().len(), 1);
+}
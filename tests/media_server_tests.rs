@@ -0,0 +1,60 @@
+//! Tests for media server refresh paths.
+
+use std::path::{Path, PathBuf};
+
+use alist_cli::media_server::{MediaServer, MediaServerKind, changed_dirs};
+
+#[test]
+fn test_server_path_mapping() {
+    let mut server = MediaServer {
+        kind: MediaServerKind::Jellyfin,
+        url: "http://localhost:8096".to_string(),
+        token: String::new(),
+        section: None,
+        local_prefix: Some(PathBuf::from("/media/strm")),
+        server_prefix: Some(PathBuf::from("/data")),
+    };
+
+    assert_eq!(
+        server.server_path(Path::new("/media/strm/movies/a")),
+        Path::new("/data/movies/a")
+    );
+    assert_eq!(
+        server.server_path(Path::new("/media/strm")),
+        Path::new("/data")
+    );
+    assert_eq!(
+        server.server_path(Path::new("/other/movies")),
+        Path::new("/other/movies")
+    );
+
+    server.server_prefix = None;
+    assert_eq!(
+        server.server_path(Path::new("/media/strm/movies")),
+        Path::new("/media/strm/movies")
+    );
+}
+
+#[test]
+fn test_changed_dirs_are_absolute() {
+    // A download root given relative to the current directory
+    let tmp = tempfile::tempdir_in(".").unwrap();
+    let root = PathBuf::from(tmp.path().file_name().unwrap());
+    std::fs::create_dir_all(root.join("movies")).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+
+    let files = [root.join("movies/a.strm"), root.join("shows/gone/b.strm")];
+    let dirs: Vec<_> = changed_dirs(&files).into_iter().collect();
+    // The removed directory is replaced by the root, its closest existing ancestor
+    assert_eq!(dirs, vec![cwd.join(&root), cwd.join(root.join("movies"))]);
+
+    let server = MediaServer {
+        kind: MediaServerKind::Jellyfin,
+        url: "http://localhost:8096".to_string(),
+        token: String::new(),
+        section: None,
+        local_prefix: Some(root.clone()),
+        server_prefix: Some(PathBuf::from("/data")),
+    };
+    assert_eq!(server.server_path(&dirs[1]), Path::new("/data/movies"));
+}
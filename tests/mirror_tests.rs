@@ -0,0 +1,175 @@
+//! Tests for mirroring a local directory to the server.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use alist_cli::{
+    AlistClient, Config,
+    mirror::{MirrorOptions, MirrorSummary},
+    utils::trash::DeleteLimit,
+};
+use anyhow::Result;
+use common::{FakeRequest, FakeResponse, entry, list_response, serve};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use percent_encoding::percent_decode_str;
+
+/// Requests that changed the fake server
+#[derive(Default)]
+struct Changes {
+    /// `File-Path` of every upload
+    uploads: Vec<String>,
+    /// Directory and names of every removal
+    removals: Vec<(String, Vec<String>)>,
+}
+
+/// Mirrors a local tree to a fake `/media`
+///
+/// The server holds `a.mkv` as uploaded, an older `b.mkv`, `old.mkv` and
+/// `sub/gone.nfo` that no longer exist locally, and a `broken` directory
+/// that cannot be listed.
+async fn mirror(options: MirrorOptions) -> (Result<MirrorSummary>, Changes) {
+    let tmp = tempfile::tempdir().unwrap();
+    let local = tmp.path();
+    std::fs::create_dir_all(local.join("broken")).unwrap();
+    for name in ["a.mkv", "b.mkv", "new.mkv", "broken/kept.mkv"] {
+        std::fs::write(local.join(name), "12345").unwrap();
+    }
+
+    let t = "2024-01-01T00:00:00Z";
+    let listings = HashMap::from([
+        (
+            "/media".to_string(),
+            vec![
+                entry("/media/a.mkv", false, 5, t),
+                entry("/media/b.mkv", false, 3, t),
+                entry("/media/old.mkv", false, 5, t),
+                entry("/media/sub", true, 0, t),
+                entry("/media/broken", true, 0, t),
+            ],
+        ),
+        (
+            "/media/sub".to_string(),
+            vec![entry("/media/sub/gone.nfo", false, 5, t)],
+        ),
+    ]);
+    let changes = Arc::new(Mutex::new(Changes::default()));
+    let server_changes = Arc::clone(&changes);
+    let address = serve(move |request: FakeRequest| {
+        let ok = || {
+            FakeResponse::json(
+                serde_json::json!({ "code": 200, "message": "success", "data": null }),
+            )
+        };
+        match request.path.as_str() {
+            "/api/fs/list" => list_response(&listings, &request),
+            "/api/fs/put" => {
+                let path = percent_decode_str(&request.headers["file-path"])
+                    .decode_utf8()
+                    .unwrap()
+                    .into_owned();
+                server_changes.lock().unwrap().uploads.push(path);
+                ok()
+            }
+            "/api/fs/remove" => {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let names = body["names"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|name| name.as_str().unwrap().to_string())
+                    .collect();
+                server_changes
+                    .lock()
+                    .unwrap()
+                    .removals
+                    .push((body["dir"].as_str().unwrap().to_string(), names));
+                ok()
+            }
+            _ => FakeResponse {
+                status: 404,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+        }
+    })
+    .await;
+
+    let client = AlistClient::new(Config {
+        server_address: address,
+        ..Config::default_test_config()
+    })
+    .unwrap();
+    let m_pb = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let result = client
+        .mirror_up(Path::new(local), "/media", options, m_pb)
+        .await;
+    let mut changes = std::mem::take(&mut *changes.lock().unwrap());
+    changes.uploads.sort();
+    changes.removals.sort();
+    (result, changes)
+}
+
+#[tokio::test]
+async fn test_mirror_uploads_changed_files() {
+    let (summary, changes) = mirror(MirrorOptions::default()).await;
+    let summary = summary.unwrap();
+
+    // Files below a directory that failed to list are uploaded again
+    assert_eq!(
+        changes.uploads,
+        vec!["/media/b.mkv", "/media/broken/kept.mkv", "/media/new.mkv"]
+    );
+    assert_eq!(summary.unchanged, 1);
+    // Without --delete extraneous files are only reported
+    assert!(changes.removals.is_empty());
+    assert_eq!(
+        summary.extraneous,
+        vec!["/media/old.mkv", "/media/sub/gone.nfo"]
+    );
+    assert!(summary.deleted.is_empty());
+}
+
+#[tokio::test]
+async fn test_mirror_deletes_extraneous_files() {
+    let (summary, changes) = mirror(MirrorOptions {
+        delete: true,
+        max_delete: Some(DeleteLimit::Count(2)),
+        ..MirrorOptions::default()
+    })
+    .await;
+    let summary = summary.unwrap();
+
+    // Nothing under the unlisted directory is removed
+    assert_eq!(
+        changes.removals,
+        vec![
+            ("/media".to_string(), vec!["old.mkv".to_string()]),
+            ("/media/sub".to_string(), vec!["gone.nfo".to_string()]),
+        ]
+    );
+    assert_eq!(
+        summary.deleted,
+        vec!["/media/old.mkv", "/media/sub/gone.nfo"]
+    );
+    assert!(summary.failed_deletes.is_empty());
+}
+
+#[tokio::test]
+async fn test_mirror_respects_max_delete() {
+    let (summary, changes) = mirror(MirrorOptions {
+        delete: true,
+        max_delete: Some(DeleteLimit::Count(1)),
+        ..MirrorOptions::default()
+    })
+    .await;
+
+    // The run is refused before anything is changed
+    assert!(summary.is_err());
+    assert!(changes.uploads.is_empty());
+    assert!(changes.removals.is_empty());
+}
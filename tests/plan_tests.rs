@@ -0,0 +1,85 @@
+//! Tests for dry-run plans.
+
+use std::path::{Path, PathBuf};
+
+use alist_cli::{
+    api::types::HashObject,
+    plan::{Plan, PlanAction, PlanFormat, format_size},
+    utils::file_ops::set_modified,
+};
+
+use common::entry;
+
+mod common;
+
+#[test]
+fn test_plan_output() {
+    let mut plan = Plan::default();
+    plan.push_entry(
+        PlanAction::CreateStrm,
+        Path::new("/lib"),
+        &entry("/tv/b.mkv", false, 3 << 30, ""),
+    );
+    plan.push_entry(
+        PlanAction::CopyMetadata,
+        Path::new("/lib"),
+        &entry("/tv/a.nfo", false, 100, ""),
+    );
+    plan.push_prune(PathBuf::from("/lib/tv/missing.strm"));
+
+    let mut table = Vec::new();
+    plan.write(&mut table, PlanFormat::Table).unwrap();
+    let table = String::from_utf8(table).unwrap();
+    let lines: Vec<_> = table.lines().collect();
+    assert!(lines[1].ends_with("/lib/tv/a.nfo"));
+    assert!(lines[2].contains("3.0 GiB") && lines[2].ends_with("/lib/tv/b.strm"));
+    assert!(lines[3].starts_with("prune") && lines[3].contains(" - "));
+    assert_eq!(lines[4], "Plan: 1 create strm, 1 copy metadata, 1 prune");
+
+    let mut json = Vec::new();
+    plan.write(&mut json, PlanFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["items"][1]["action"], "create_strm");
+    assert_eq!(json["items"][1]["remote_path"], "/tv/b.mkv");
+
+    assert_eq!(format_size(512), "512 B");
+}
+
+#[tokio::test]
+async fn test_plan_download_skips_current_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let t = "2024-01-01T00:00:00Z";
+    std::fs::create_dir_all(root.join("tv")).unwrap();
+    std::fs::write(root.join("tv/current.mkv"), "12345").unwrap();
+    set_modified(&root.join("tv/current.mkv"), t).await.unwrap();
+    std::fs::write(root.join("tv/touched.mkv"), "12345").unwrap();
+    std::fs::write(root.join("tv/resized.mkv"), "123").unwrap();
+
+    let mut hashed = entry("/tv/touched.mkv", false, 5, t);
+    hashed.entry.hash_info = Some(HashObject::Md5 {
+        md5: "827ccb0eea8a706c4c34a16891f84e7b".to_string(),
+    });
+    let mut plan = Plan::default();
+    plan.push_download(root, &entry("/tv/current.mkv", false, 5, t))
+        .await;
+    plan.push_download(root, &hashed).await;
+    plan.push_download(root, &entry("/tv/resized.mkv", false, 5, t))
+        .await;
+    plan.push_download(root, &entry("/tv/new.mkv", false, 5, t))
+        .await;
+
+    let actions: Vec<_> = plan
+        .items
+        .iter()
+        .map(|item| (item.action, item.remote_path.as_deref().unwrap()))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            (PlanAction::Verify, "/tv/touched.mkv"),
+            (PlanAction::Download, "/tv/resized.mkv"),
+            (PlanAction::Download, "/tv/new.mkv"),
+        ]
+    );
+}
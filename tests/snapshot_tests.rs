@@ -0,0 +1,147 @@
+//! Tests for the incremental scan snapshot.
+
+use std::{collections::HashMap, sync::Arc};
+
+use alist_cli::{AlistClient, Config, snapshot::Snapshot};
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+
+use common::{FakeResponse, entry, list_response, run_cli, serve, serve_listings};
+
+mod common;
+
+#[test]
+fn test_listing_reused_only_if_unmodified() {
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(entry("/movies", true, 100, "t1"));
+    snapshot.insert(entry("/movies/a.mkv", false, 100, "t1"));
+
+    let listing: Vec<_> = snapshot
+        .listing("/movies", "t1")
+        .unwrap()
+        .map(|e| e.path_str.clone())
+        .collect();
+    assert_eq!(listing, vec!["/movies/a.mkv"]);
+    assert!(snapshot.listing("/movies", "t2").is_none());
+    assert!(snapshot.listing("/shows", "t1").is_none());
+
+    snapshot.forget_dir("/movies");
+    assert!(snapshot.listing("/movies", "t1").is_none());
+}
+
+#[test]
+fn test_forget_entry() {
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(entry("/movies", true, 0, "t1"));
+    snapshot.insert(entry("/movies/a.jpg", false, 100, "t1"));
+    snapshot.insert(entry("/movies/b.jpg", false, 100, "t1"));
+
+    snapshot.forget("/movies/a.jpg");
+    assert!(!snapshot.is_unchanged(&entry("/movies/a.jpg", false, 100, "t1")));
+    assert!(snapshot.is_unchanged(&entry("/movies/b.jpg", false, 100, "t1")));
+    // The listing lacks the entry now, so it must not be reused
+    assert!(snapshot.listing("/movies", "t1").is_none());
+}
+
+#[test]
+fn test_is_unchanged() {
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(entry("/a.mkv", false, 100, "t1"));
+
+    assert!(snapshot.is_unchanged(&entry("/a.mkv", false, 100, "t1")));
+    assert!(!snapshot.is_unchanged(&entry("/a.mkv", false, 100, "t2")));
+    assert!(!snapshot.is_unchanged(&entry("/b.mkv", false, 100, "t1")));
+
+    let mut resized = entry("/a.mkv", false, 100, "t1");
+    resized.entry.size = 200;
+    assert!(!snapshot.is_unchanged(&resized));
+}
+
+#[tokio::test]
+async fn test_incremental_scan_finds_changes_below_unchanged_dir() {
+    // `/lib/show` is unchanged, but a new episode was added to its season
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(entry("/lib/show", true, 0, "t1"));
+    snapshot.insert(entry("/lib/show/s1", true, 0, "t1"));
+    snapshot.insert(entry("/lib/show/s1/e1.mkv", false, 100, "t1"));
+
+    let listings = HashMap::from([
+        ("/lib".to_string(), vec![entry("/lib/show", true, 0, "t1")]),
+        (
+            "/lib/show/s1".to_string(),
+            vec![
+                entry("/lib/show/s1/e1.mkv", false, 100, "t1"),
+                entry("/lib/show/s1/e2.mkv", false, 100, "t2"),
+            ],
+        ),
+    ]);
+    let client = AlistClient::new(Config {
+        server_address: serve_listings(listings).await,
+        ..Config::default_test_config()
+    })
+    .unwrap();
+
+    let m_pb = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    let mut paths: Vec<_> = client
+        .stream_path_structure_incremental("/lib".to_string(), Arc::new(snapshot), m_pb)
+        .map(|entry| entry.unwrap().path_str)
+        .collect()
+        .await;
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "/lib/show",
+            "/lib/show/s1",
+            "/lib/show/s1/e1.mkv",
+            "/lib/show/s1/e2.mkv"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_failed_fetch_not_recorded() {
+    let tmp = tempfile::tempdir().unwrap();
+    let local = tmp.path().join("local");
+    // An old poster the changed remote one failed to replace
+    let poster = local.join("lib/poster.jpg");
+    std::fs::create_dir_all(poster.parent().unwrap()).unwrap();
+    std::fs::write(&poster, "old").unwrap();
+
+    let listings = HashMap::from([
+        (
+            "/".to_string(),
+            vec![entry("/lib", true, 0, "2024-06-01T00:00:00Z")],
+        ),
+        (
+            "/lib".to_string(),
+            vec![entry("/lib/poster.jpg", false, 5, "2024-06-01T00:00:00Z")],
+        ),
+    ]);
+    let server = serve(move |request| match request.path.as_str() {
+        "/api/fs/list" => list_response(&listings, &request),
+        _ => FakeResponse::json(serde_json::json!({
+            "code": 500,
+            "message": "storage unavailable",
+            "data": null,
+        })),
+    })
+    .await;
+    let output = run_cli(
+        tmp.path(),
+        &[
+            "-s",
+            &server,
+            "auto-sym",
+            "--local-path",
+            local.to_str().unwrap(),
+        ],
+    )
+    .await;
+    assert!(output.status.success(), "{output:?}");
+
+    let snapshot = Snapshot::load(&Snapshot::path_for(&local)).await.unwrap();
+    assert!(!snapshot.is_unchanged(&entry("/lib/poster.jpg", false, 5, "2024-06-01T00:00:00Z")));
+    assert!(snapshot.listing("/lib", "2024-06-01T00:00:00Z").is_none());
+    assert_eq!(std::fs::read_to_string(&poster).unwrap(), "old");
+}
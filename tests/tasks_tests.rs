@@ -0,0 +1,49 @@
+//! Tests for server task states and the task table.
+
+use alist_cli::api::{
+    tasks::{TaskKind, TaskState, write_task_table},
+    types::TaskInfo,
+};
+
+fn task(id: &str, state: u32, error: &str) -> TaskInfo {
+    TaskInfo {
+        id: id.to_string(),
+        name: format!("copy {id}"),
+        state,
+        status: String::new(),
+        progress: 42.0,
+        total_bytes: 0,
+        error: error.to_string(),
+    }
+}
+
+#[test]
+fn test_task_states() {
+    assert_eq!(task("a", 1, "").task_state(), TaskState::Running);
+    assert!(!TaskState::Errored.is_finished());
+    assert!(TaskState::Succeeded.is_finished());
+    assert!(TaskState::from(7).is_finished());
+    assert_eq!(TaskState::from(42), TaskState::Other(42));
+    assert_eq!(TaskState::WaitingRetry.to_string(), "waiting retry");
+}
+
+#[test]
+fn test_write_task_table() {
+    let tasks = vec![
+        (TaskKind::Copy, task("c1", 1, "")),
+        (TaskKind::OfflineDownload, task("o1", 7, "connection reset")),
+    ];
+    let mut out = Vec::new();
+    write_task_table(&mut out, &tasks).unwrap();
+    let table = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = table.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("KIND              ID  STATE"));
+    assert!(lines[1].starts_with("copy              c1  running"));
+    assert!(lines[2].contains("failed") && lines[2].contains("42.0%"));
+    assert!(lines[3].ends_with("error: connection reset"));
+
+    let mut out = Vec::new();
+    write_task_table(&mut out, &[]).unwrap();
+    assert!(String::from_utf8(out).unwrap().ends_with("No tasks\n"));
+}
@@ -0,0 +1,123 @@
+//! Tests for pruning limits and the trash directory.
+
+use std::collections::HashMap;
+
+use alist_cli::utils::trash::{DEFAULT_TRASH_DIR, DeleteLimit, Trash};
+
+use common::{entry, run_cli, serve_listings};
+
+mod common;
+
+#[test]
+fn test_delete_limit_parse() {
+    assert_eq!(
+        "500".parse::<DeleteLimit>().unwrap(),
+        DeleteLimit::Count(500)
+    );
+    assert_eq!(
+        "10%".parse::<DeleteLimit>().unwrap(),
+        DeleteLimit::Percent(10.0)
+    );
+    assert_eq!(
+        " 2.5 % ".parse::<DeleteLimit>().unwrap(),
+        DeleteLimit::Percent(2.5)
+    );
+
+    assert!("150%".parse::<DeleteLimit>().is_err());
+    assert!("-1".parse::<DeleteLimit>().is_err());
+    assert!("many".parse::<DeleteLimit>().is_err());
+}
+
+#[test]
+fn test_delete_limit_is_exceeded() {
+    let count = DeleteLimit::Count(10);
+    assert!(!count.is_exceeded(10, 20));
+    assert!(count.is_exceeded(11, 20));
+
+    let percent = DeleteLimit::Percent(10.0);
+    assert!(!percent.is_exceeded(10, 100));
+    assert!(percent.is_exceeded(11, 100));
+    // Nothing to compare against when the local tree is empty
+    assert!(!percent.is_exceeded(0, 0));
+}
+
+#[tokio::test]
+async fn test_trash_move_and_restore() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let local = root.join("local");
+    let file = local.join("show/s01e01.strm");
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    std::fs::write(&file, "http://example.com/s01e01.mkv").unwrap();
+
+    let trash = Trash::new(root.join("trash"));
+    let batch_dir = trash.new_batch().await.unwrap();
+    Trash::move_file(&batch_dir, &local, &file).await.unwrap();
+    assert!(!file.exists());
+
+    let batches = trash.batches().await.unwrap();
+    assert_eq!(batches.len(), 1);
+
+    let restored = trash.restore(&batches[0], &local).await.unwrap();
+    assert_eq!(restored, 1);
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "http://example.com/s01e01.mkv"
+    );
+    assert!(trash.batches().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_batches_never_collide() {
+    let tmp = tempfile::tempdir().unwrap();
+    let trash = Trash::new(tmp.path().join("trash"));
+
+    // Runs started within the same second get batches of their own
+    let first = trash.new_batch().await.unwrap();
+    let second = trash.new_batch().await.unwrap();
+    assert_ne!(first, second);
+    assert!(first.is_dir() && second.is_dir());
+
+    // Both are dated, so purging old batches keeps them
+    let purged = trash.purge(Some(chrono::Duration::days(1))).await.unwrap();
+    assert!(purged.is_empty());
+    assert_eq!(trash.batches().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_prune_without_trash_keeps_batches() {
+    let tmp = tempfile::tempdir().unwrap();
+    let local = tmp.path().join("local");
+    let gone = local.join("movies/gone.strm");
+    std::fs::create_dir_all(gone.parent().unwrap()).unwrap();
+    std::fs::write(&gone, "http://example.com/gone.mkv").unwrap();
+
+    // A batch left by an earlier run with --trash
+    let trash = Trash::new(local.join(DEFAULT_TRASH_DIR));
+    let batch_dir = trash.new_batch().await.unwrap();
+    let trashed = batch_dir.join("movies/old.strm");
+    std::fs::create_dir_all(trashed.parent().unwrap()).unwrap();
+    std::fs::write(&trashed, "http://example.com/old.mkv").unwrap();
+
+    let listings = HashMap::from([
+        ("/".to_string(), vec![entry("/movies", true, 0, "t1")]),
+        ("/movies".to_string(), Vec::new()),
+    ]);
+    let server = serve_listings(listings).await;
+    let output = run_cli(
+        tmp.path(),
+        &[
+            "-s",
+            &server,
+            "auto-sym",
+            "--local-path",
+            local.to_str().unwrap(),
+            "--delete",
+        ],
+    )
+    .await;
+    assert!(output.status.success(), "{output:?}");
+
+    assert!(!gone.exists());
+    assert!(trashed.exists());
+}
@@ -0,0 +1,32 @@
+//! Tests for upload path mapping.
+
+use std::path::PathBuf;
+
+use alist_cli::upload::upload_targets;
+
+#[test]
+fn test_upload_targets() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    std::fs::create_dir_all(root.join("season 1")).unwrap();
+    std::fs::write(root.join("a.mkv"), b"a").unwrap();
+    std::fs::write(root.join("season 1/e01.mkv"), b"e").unwrap();
+
+    // A directory's contents go below the remote directory
+    assert_eq!(
+        upload_targets(&root, "/media/").unwrap(),
+        vec![
+            (root.join("a.mkv"), "/media/a.mkv".to_string()),
+            (
+                root.join("season 1/e01.mkv"),
+                "/media/season 1/e01.mkv".to_string()
+            ),
+        ]
+    );
+    // A single file keeps its name
+    assert_eq!(
+        upload_targets(&root.join("a.mkv"), "/").unwrap(),
+        vec![(root.join("a.mkv"), "/a.mkv".to_string())]
+    );
+    assert!(upload_targets(&PathBuf::from("/nonexistent/alist_cli"), "/").is_err());
+}